@@ -53,8 +53,8 @@ func _physics_process(_delta):
 			return
 		
 		$GodotGGRS.advance_frame(local_handle, raw_input_to_int("con1")) # raw_input_to_int is a method that parses InputActions that start with "con1" into a integer.
-		var net_stats: Array = $GodotGGRS.get_network_stats(remote_handle)
-		$NetStats.text = "Send queue len : %f\nPing : %f\nKbps sent : %f\nLocal frames behind : %f\nRemote frames behind : %f" % net_stats
+		var net_stats: Dictionary = $GodotGGRS.get_network_stats(remote_handle)
+		$NetStats.text = "Send queue len : %f\nPing : %f\nKbps sent : %f\nLocal frames behind : %f\nRemote frames behind : %f" % [net_stats.get("send_queue_len", 0), net_stats.get("ping", 0), net_stats.get("kbps_sent", 0), net_stats.get("local_frames_behind", 0), net_stats.get("remote_frames_behind", 0)]
 
 func raw_input_to_int(prefix: String)->int:
 	# This method is how i parse InputActions into an int, but as long as it's an int it doesn't matter how it's parsed.
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Which IP version is preferred when a hostname resolves to multiple addresses.
+/// In Godot a preference is written as a String: `"ipv4"`, `"ipv6"` or `"any"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// Use the first address that was resolved.
+    Any,
+    /// Use the first IPv4 address, falling back to the first address if there is none.
+    Ipv4,
+    /// Use the first IPv6 address, falling back to the first address if there is none.
+    Ipv6,
+}
+
+impl Default for AddressPreference {
+    /// GGRS binds its socket to an IPv4 address, so IPv4 addresses are preferred by default.
+    fn default() -> Self {
+        AddressPreference::Ipv4
+    }
+}
+
+impl AddressPreference {
+    /// Parses a preference from its String representation.
+    pub fn parse(preference: &str) -> Option<Self> {
+        match preference.trim().to_ascii_lowercase().as_str() {
+            "any" => Some(AddressPreference::Any),
+            "ipv4" => Some(AddressPreference::Ipv4),
+            "ipv6" => Some(AddressPreference::Ipv6),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, addr: &SocketAddr) -> bool {
+        match self {
+            AddressPreference::Any => true,
+            AddressPreference::Ipv4 => addr.is_ipv4(),
+            AddressPreference::Ipv6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// Errors that can occur while resolving an address String.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address could not be resolved, contains the address and the reason.
+    ResolutionFailed(String, String),
+    /// The address was resolved but did not return any socket addresses.
+    NoAddresses(String),
+    /// The address to bind a socket to is not a port or a literal socket address, contains the address.
+    InvalidBindAddress(String),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::ResolutionFailed(address, reason) => {
+                write!(f, "Could not resolve address \"{}\": {}.", address, reason)
+            }
+            AddressError::NoAddresses(address) => {
+                write!(
+                    f,
+                    "Address \"{}\" did not resolve to any socket address.",
+                    address
+                )
+            }
+            AddressError::InvalidBindAddress(address) => {
+                write!(
+                    f,
+                    "\"{}\" is not a port or a socket address like \"0.0.0.0:7070\" or \"[::]:7070\".",
+                    address
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Resolves an address String like `"127.0.0.1:7070"`, `"[::1]:7070"` or `"localhost:7070"` into a [SocketAddr].
+/// Literal socket addresses are returned as-is, hostnames are resolved through [ToSocketAddrs].
+pub fn resolve_socket_addr(
+    address: &str,
+    preference: AddressPreference,
+) -> Result<SocketAddr, AddressError> {
+    let address = address.trim();
+    if let Ok(addr) = address.parse() {
+        return Ok(addr);
+    }
+
+    let resolved: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .map_err(|e| AddressError::ResolutionFailed(address.to_owned(), e.to_string()))?
+        .collect();
+    resolved
+        .iter()
+        .find(|addr| preference.matches(addr))
+        .or_else(|| resolved.first())
+        .copied()
+        .ok_or_else(|| AddressError::NoAddresses(address.to_owned()))
+}
+
+/// Returns the address a socket binds to when only a port is given, which is the port on all IPv4 interfaces just like GGRS does by default.
+pub fn bind_addr_from_port(port: i64) -> Result<SocketAddr, AddressError> {
+    match u16::try_from(port) {
+        Ok(port) => Ok(SocketAddr::from(([0, 0, 0, 0], port))),
+        Err(_) => Err(AddressError::InvalidBindAddress(port.to_string())),
+    }
+}
+
+/// Parses the address a socket binds to, like `"0.0.0.0:7070"`, `"[::]:7070"` or `"192.168.1.5:0"`.
+/// A String containing only a port is bound on all IPv4 interfaces, hostnames are not resolved since a socket can only bind to a local interface.
+pub fn parse_bind_addr(address: &str) -> Result<SocketAddr, AddressError> {
+    let address = address.trim();
+    match address.parse::<i64>() {
+        Ok(port) => bind_addr_from_port(port),
+        Err(_) => address
+            .parse()
+            .map_err(|_| AddressError::InvalidBindAddress(address.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ports_and_bind_addresses() {
+        assert_eq!(parse_bind_addr("7070"), Ok("0.0.0.0:7070".parse().unwrap()));
+        assert_eq!(
+            parse_bind_addr(" [::]:7070 "),
+            Ok("[::]:7070".parse().unwrap())
+        );
+        assert_eq!(
+            parse_bind_addr("192.168.1.5:0"),
+            Ok("192.168.1.5:0".parse().unwrap())
+        );
+        assert!(parse_bind_addr("70700").is_err());
+        assert!(parse_bind_addr("localhost:7070").is_err());
+        assert!(bind_addr_from_port(-1).is_err());
+    }
+
+    #[test]
+    fn parses_literal_bind_addresses_only() {
+        assert_eq!(
+            parse_bind_addr("[::]:7070"),
+            Ok("[::]:7070".parse().unwrap())
+        );
+        assert_eq!(
+            parse_bind_addr("0.0.0.0:0"),
+            Ok("0.0.0.0:0".parse().unwrap())
+        );
+        assert_eq!(
+            parse_bind_addr("7070.9"),
+            Err(AddressError::InvalidBindAddress("7070.9".to_owned()))
+        );
+        assert!(parse_bind_addr("").is_err());
+        assert!(bind_addr_from_port(65536).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! Plays a replay recorded with `GodotGGRSP2PSession.start_recording()` through a `SyncTestSession` and reports the first frame of which the checksum mismatched.
+//! The inputs are fed to an [InputHashSimulation], which validates the inputs and the order of requests without launching Godot.
+//!
+//! Usage: `verify_replay <replay file> [check distance]`, the check distance defaults to the max prediction of the replay minus one.
+//! Exits with `0` when the replay is deterministic, `1` on a mismatch and `2` when the replay could not be verified.
+
+use godot_ggrs_wrapper::replay::Replay;
+use godot_ggrs_wrapper::verification::{self, InputHashSimulation, Verification};
+use std::fs::File;
+use std::io::BufReader;
+use std::process::exit;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let path = match args.get(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: verify_replay <replay file> [check distance]");
+            exit(2);
+        }
+    };
+    let replay = match File::open(path) {
+        Ok(file) => Replay::read_from(&mut BufReader::new(file)),
+        Err(e) => {
+            eprintln!("Could not open \"{}\": {}.", path, e);
+            exit(2);
+        }
+    };
+    let replay = match replay {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    };
+    let check_distance = match args.get(2).map(|arg| arg.parse::<usize>()) {
+        Some(Ok(check_distance)) => check_distance,
+        Some(Err(_)) => {
+            eprintln!("The check distance should be a positive integer.");
+            exit(2);
+        }
+        None => replay.header.max_prediction.saturating_sub(1),
+    };
+
+    let mut simulation = InputHashSimulation::new(&replay);
+    match verification::verify_replay(&replay, &mut simulation, check_distance) {
+        Ok(Verification::Mismatch(frame)) => {
+            println!("Checksum mismatch at frame {}.", frame);
+            exit(1);
+        }
+        Ok(Verification::Deterministic(frames)) => match simulation.mismatched_frames().first() {
+            Some(frame) => {
+                println!(
+                    "The inputs of frame {} differ from the recorded inputs.",
+                    frame
+                );
+                exit(1);
+            }
+            None => println!("Verified {} frames without mismatches.", frames),
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Detecting desyncs by comparing the checksums of confirmed frames with the checksums reported by peers.
+
+use crate::wrapper_socket::ChecksumReport;
+use ggrs::{Frame, PlayerHandle, NULL_FRAME};
+use std::collections::{BTreeMap, HashMap};
+
+/// The amount of confirmed frames of which checksums are kept around to compare with late reports of peers.
+const MAX_CHECKSUM_HISTORY: i32 = 128;
+
+/// Calculates the 64-bit FNV-1a hash of a buffer, which is used as the checksum of a saved state.
+pub fn checksum(buffer: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    buffer.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// A confirmed frame of which the local checksum differs from the checksum reported by a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    /// The frame at which the simulations diverged.
+    pub frame: Frame,
+    /// The checksum of the local state.
+    pub local_checksum: u64,
+    /// The checksum reported by the peer.
+    pub remote_checksum: u64,
+    /// The handle of the peer.
+    pub handle: PlayerHandle,
+}
+
+/// Compares the checksums of confirmed frames with the checksums reported by peers.
+#[derive(Debug, Clone)]
+pub struct DesyncDetector {
+    local_checksums: BTreeMap<Frame, u64>,
+    remote_checksums: HashMap<PlayerHandle, BTreeMap<Frame, u64>>,
+    confirmed_frame: Frame,
+    last_reported_frame: Frame,
+}
+
+impl Default for DesyncDetector {
+    fn default() -> Self {
+        DesyncDetector {
+            local_checksums: BTreeMap::new(),
+            remote_checksums: HashMap::new(),
+            confirmed_frame: NULL_FRAME,
+            last_reported_frame: NULL_FRAME,
+        }
+    }
+}
+
+impl DesyncDetector {
+    /// Creates an empty [DesyncDetector].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the checksum of a saved state, states that are saved again after a rollback replace the previous checksum.
+    pub fn record_local(&mut self, frame: Frame, checksum: u64) {
+        if frame > self.confirmed_frame {
+            self.local_checksums.insert(frame, checksum);
+        }
+    }
+
+    /// Records a checksum that was reported by a peer.
+    pub fn record_remote(&mut self, handle: PlayerHandle, report: ChecksumReport) {
+        if report.frame > self.confirmed_frame - MAX_CHECKSUM_HISTORY {
+            self.remote_checksums
+                .entry(handle)
+                .or_default()
+                .insert(report.frame, report.checksum);
+        }
+    }
+
+    /// Marks every frame up to and including `confirmed_frame` as confirmed, after which their checksums can no longer change.
+    /// Returns the reports of newly confirmed frames, which should be sent to all peers.
+    pub fn confirm(&mut self, confirmed_frame: Frame) -> Vec<ChecksumReport> {
+        self.confirmed_frame = self.confirmed_frame.max(confirmed_frame);
+        if self.last_reported_frame >= self.confirmed_frame {
+            return Vec::new();
+        }
+        let reports = self
+            .local_checksums
+            .range(self.last_reported_frame + 1..=self.confirmed_frame)
+            .map(|(frame, checksum)| ChecksumReport {
+                frame: *frame,
+                checksum: *checksum,
+            })
+            .collect::<Vec<_>>();
+        if let Some(last) = reports.last() {
+            self.last_reported_frame = last.frame;
+        }
+        reports
+    }
+
+    /// Compares all confirmed local checksums with the reports of peers and returns every mismatch.
+    /// Compared and outdated checksums are discarded.
+    pub fn detect(&mut self) -> Vec<Desync> {
+        let mut desyncs = Vec::new();
+        for (handle, remote_checksums) in &mut self.remote_checksums {
+            remote_checksums.retain(|frame, remote_checksum| {
+                if *frame > self.confirmed_frame {
+                    return true;
+                }
+                match self.local_checksums.get(frame) {
+                    Some(local_checksum) if local_checksum != remote_checksum => {
+                        desyncs.push(Desync {
+                            frame: *frame,
+                            local_checksum: *local_checksum,
+                            remote_checksum: *remote_checksum,
+                            handle: *handle,
+                        })
+                    }
+                    _ => (),
+                }
+                false
+            });
+        }
+
+        let oldest_frame = self.confirmed_frame - MAX_CHECKSUM_HISTORY;
+        self.local_checksums = self.local_checksums.split_off(&oldest_frame);
+        desyncs.sort_by_key(|desync| (desync.frame, desync.handle));
+        desyncs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(frame: Frame, checksum: u64) -> ChecksumReport {
+        ChecksumReport { frame, checksum }
+    }
+
+    #[test]
+    fn matching_reports_are_silent() {
+        let mut detector = DesyncDetector::new();
+        detector.record_local(0, 5);
+        detector.record_local(1, 6);
+        assert_eq!(detector.confirm(1), vec![report(0, 5), report(1, 6)]);
+        detector.record_remote(1, report(0, 5));
+        detector.record_remote(1, report(1, 6));
+        assert!(detector.detect().is_empty());
+    }
+
+    #[test]
+    fn mismatching_reports_are_detected() {
+        let mut detector = DesyncDetector::new();
+        detector.record_local(3, 10);
+        detector.confirm(3);
+        detector.record_remote(2, report(3, 11));
+        assert_eq!(
+            detector.detect(),
+            vec![Desync {
+                frame: 3,
+                local_checksum: 10,
+                remote_checksum: 11,
+                handle: 2,
+            }]
+        );
+        //Compared reports are discarded, so every desync is only detected once
+        assert!(detector.detect().is_empty());
+    }
+
+    #[test]
+    fn reports_of_unconfirmed_frames_are_compared_once_confirmed() {
+        let mut detector = DesyncDetector::new();
+        detector.record_local(5, 1);
+        detector.record_remote(1, report(5, 2));
+        detector.confirm(4);
+        assert!(detector.detect().is_empty());
+        detector.confirm(5);
+        assert_eq!(detector.detect().len(), 1);
+    }
+
+    #[test]
+    fn states_saved_again_after_a_rollback_replace_the_checksum() {
+        let mut detector = DesyncDetector::new();
+        detector.record_local(2, 1);
+        detector.record_local(2, 7);
+        assert_eq!(detector.confirm(2), vec![report(2, 7)]);
+        //Confirmed frames can no longer change and are only reported once
+        detector.record_local(2, 9);
+        assert!(detector.confirm(2).is_empty());
+        detector.record_remote(1, report(2, 7));
+        assert!(detector.detect().is_empty());
+    }
+
+    #[test]
+    fn old_frames_are_dropped() {
+        let mut detector = DesyncDetector::new();
+        for frame in 0..200 {
+            detector.record_local(frame, frame as u64);
+        }
+        detector.confirm(199);
+        assert!(detector.detect().is_empty());
+        assert_eq!(
+            detector.local_checksums.keys().next(),
+            Some(&(199 - MAX_CHECKSUM_HISTORY))
+        );
+        //Reports of dropped frames are ignored instead of being compared with missing checksums
+        detector.record_remote(1, report(10, 0));
+        detector.record_remote(1, report(150, 0));
+        assert!(detector.remote_checksums[&1].keys().eq([&150]));
+        assert_eq!(detector.detect().len(), 1);
+    }
+}
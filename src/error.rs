@@ -0,0 +1,216 @@
+use crate::address::AddressError;
+use crate::input_schema::InputSchemaError;
+use crate::replay::ReplayError;
+use crate::*;
+use gdnative::nativescript::user_data::Map;
+use ggrs::{GGRSError, PlayerHandle};
+use std::fmt;
+
+/// The error code that is reported when no error has occurred.
+pub const ERR_CODE_OK: i64 = 0;
+
+/// The names and codes of all errors, these are registered as constants on every session class.
+pub const ERR_CODES: &[(&str, i64)] = &[
+    ("ERR_OK", ERR_CODE_OK),
+    ("ERR_NO_SESSION", 1),
+    ("ERR_NO_CALLBACK_NODE", 2),
+    ("ERR_BAD_ADDRESS", 3),
+    ("ERR_BAD_INPUT_SIZE", 4),
+    ("ERR_NO_INPUT_SCHEMA", 5),
+    ("ERR_BAD_INPUT_SCHEMA", 6),
+    ("ERR_REPLAY_FILE", 7),
+    ("ERR_INCOMPATIBLE_PEER", 8),
+    ("ERR_NOT_LOCAL_PLAYER", 9),
+    ("ERR_INVALID_HANDLE", 10),
+    ("ERR_PREDICTION_THRESHOLD", 11),
+    ("ERR_INVALID_REQUEST", 12),
+    ("ERR_MISMATCHED_CHECKSUM", 13),
+    ("ERR_SOCKET_CREATION_FAILED", 14),
+    ("ERR_NOT_SYNCHRONIZED", 15),
+    ("ERR_PLAYER_DISCONNECTED", 16),
+    ("ERR_SPECTATOR_TOO_FAR_BEHIND", 17),
+];
+
+/// All errors that can be reported by the session classes.
+/// Every error has an integer code, see [ERR_CODES] for the names under which these are exposed to Godot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GodotGGRSError {
+    /// No session was made.
+    NoSession,
+    /// No callback node or rollback participants were specified.
+    NoCallbackNode,
+    /// An address could not be resolved or an address preference is unknown, contains the reason.
+    BadAddress(String),
+    /// The given input does not match the input size of the session.
+    BadInputSize,
+    /// Inputs were given as a `Dictionary` but no input schema was set.
+    NoInputSchema,
+    /// The input schema could not be built or an input did not fit the schema.
+    BadInputSchema(InputSchemaError),
+    /// A replay file could not be read or written, or no replay was loaded, contains the reason.
+    ReplayFile(String),
+    /// A message was received from a peer running another version of GodotGGRS, contains the address of the peer.
+    IncompatiblePeer(String),
+    /// A request that is only valid for local players was made for another player, contains the handle.
+    NotLocalPlayer(PlayerHandle),
+    /// An error returned by GGRS.
+    Ggrs(GGRSError),
+}
+
+impl GodotGGRSError {
+    /// Returns the integer code of the error.
+    pub fn code(&self) -> i64 {
+        match self {
+            GodotGGRSError::NoSession => 1,
+            GodotGGRSError::NoCallbackNode => 2,
+            GodotGGRSError::BadAddress(_) => 3,
+            GodotGGRSError::BadInputSize => 4,
+            GodotGGRSError::NoInputSchema => 5,
+            GodotGGRSError::BadInputSchema(_) => 6,
+            GodotGGRSError::ReplayFile(_) => 7,
+            GodotGGRSError::IncompatiblePeer(_) => 8,
+            GodotGGRSError::NotLocalPlayer(_) => 9,
+            GodotGGRSError::Ggrs(e) => match e {
+                GGRSError::InvalidHandle => 10,
+                GGRSError::PredictionThreshold => 11,
+                GGRSError::InvalidRequest { .. } => 12,
+                GGRSError::MismatchedChecksum { .. } => 13,
+                GGRSError::SocketCreationFailed => 14,
+                GGRSError::NotSynchronized => 15,
+                GGRSError::PlayerDisconnected => 16,
+                GGRSError::SpectatorTooFarBehind => 17,
+            },
+        }
+    }
+}
+
+impl fmt::Display for GodotGGRSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GodotGGRSError::NoSession => write!(f, "{}", ERR_MESSAGE_NO_SESSION_MADE),
+            GodotGGRSError::NoCallbackNode => write!(f, "{}", ERR_MESSAGE_NO_CALLBACK_NODE),
+            GodotGGRSError::BadAddress(reason) => write!(f, "{}", reason),
+            GodotGGRSError::BadInputSize => write!(f, "{}", ERR_MESSAGE_INPUT_SIZE_MISMATCH),
+            GodotGGRSError::NoInputSchema => write!(f, "{}", ERR_MESSAGE_NO_INPUT_SCHEMA),
+            GodotGGRSError::BadInputSchema(e) => write!(f, "{}", e),
+            GodotGGRSError::ReplayFile(reason) => write!(f, "{}", reason),
+            GodotGGRSError::IncompatiblePeer(addr) => write!(
+                f,
+                "Received a message from {} which does not use the same version of GodotGGRS, all peers and spectators need to use the same version.",
+                addr
+            ),
+            GodotGGRSError::NotLocalPlayer(handle) => {
+                write!(f, "The player with handle {} is not a local player.", handle)
+            }
+            GodotGGRSError::Ggrs(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for GodotGGRSError {}
+
+impl From<GGRSError> for GodotGGRSError {
+    fn from(e: GGRSError) -> Self {
+        GodotGGRSError::Ggrs(e)
+    }
+}
+
+impl From<InputSchemaError> for GodotGGRSError {
+    fn from(e: InputSchemaError) -> Self {
+        GodotGGRSError::BadInputSchema(e)
+    }
+}
+
+impl From<ReplayError> for GodotGGRSError {
+    fn from(e: ReplayError) -> Self {
+        GodotGGRSError::ReplayFile(e.to_string())
+    }
+}
+
+impl From<AddressError> for GodotGGRSError {
+    fn from(e: AddressError) -> Self {
+        GodotGGRSError::BadAddress(e.to_string())
+    }
+}
+
+/// Registers all [ERR_CODES] as read-only integer properties, so they can be used like `session.ERR_NO_SESSION` inside Godot.
+pub fn register_error_codes<C>(builder: &ClassBuilder<C>)
+where
+    C: NativeClass,
+    C::UserData: Map,
+{
+    register_constants(builder, ERR_CODES);
+}
+
+/// Registers named integer codes as read-only properties, so they can be used like constants inside Godot.
+pub fn register_constants<C>(builder: &ClassBuilder<C>, constants: &[(&str, i64)])
+where
+    C: NativeClass,
+    C::UserData: Map,
+{
+    for (name, code) in constants {
+        let code = *code;
+        builder
+            .add_property::<i64>(name)
+            .with_default(code)
+            .with_usage(PropertyUsage::empty())
+            .with_getter(move |_, _| code)
+            .done();
+    }
+}
+
+/// Returns the code of the last error, or [ERR_CODE_OK] when there is none.
+pub fn last_error_code(last_error: &Option<GodotGGRSError>) -> i64 {
+    last_error
+        .as_ref()
+        .map_or(ERR_CODE_OK, GodotGGRSError::code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One error of every kind, extend this when adding a variant to [GodotGGRSError] or [GGRSError].
+    fn all_errors() -> Vec<GodotGGRSError> {
+        vec![
+            GodotGGRSError::NoSession,
+            GodotGGRSError::NoCallbackNode,
+            GodotGGRSError::BadAddress(String::new()),
+            GodotGGRSError::BadInputSize,
+            GodotGGRSError::NoInputSchema,
+            GodotGGRSError::BadInputSchema(InputSchemaError::Empty),
+            GodotGGRSError::ReplayFile(String::new()),
+            GodotGGRSError::IncompatiblePeer(String::new()),
+            GodotGGRSError::NotLocalPlayer(0),
+            GGRSError::InvalidHandle.into(),
+            GGRSError::PredictionThreshold.into(),
+            GGRSError::InvalidRequest {
+                info: String::new(),
+            }
+            .into(),
+            GGRSError::MismatchedChecksum { frame: 0 }.into(),
+            GGRSError::SocketCreationFailed.into(),
+            GGRSError::NotSynchronized.into(),
+            GGRSError::PlayerDisconnected.into(),
+            GGRSError::SpectatorTooFarBehind.into(),
+        ]
+    }
+
+    #[test]
+    fn every_error_code_is_registered() {
+        let errors = all_errors();
+        for error in &errors {
+            assert!(
+                ERR_CODES.iter().any(|(_, code)| *code == error.code()),
+                "{:?} has no registered code",
+                error
+            );
+        }
+        //Every registered code besides ERR_OK belongs to exactly one kind of error
+        let mut codes: Vec<i64> = errors.iter().map(GodotGGRSError::code).collect();
+        codes.push(ERR_CODE_OK);
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), ERR_CODES.len());
+    }
+}
@@ -0,0 +1,114 @@
+//! Recommending the frame delay of local players from the ping to remote players and the depth of rollbacks.
+
+use std::time::Duration;
+
+/// The frames per second GGRS assumes when no other value was set.
+pub const DEFAULT_FPS: u32 = 60;
+/// The highest frame delay that is ever recommended, more delay makes the game feel unresponsive.
+pub const MAX_RECOMMENDED_FRAME_DELAY: u32 = 8;
+/// How often the ping to remote players is sampled when no network stats interval was set.
+pub const PING_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// Rollbacks up to this depth are cheap and rarely visible, so they are not worth an extra frame of delay.
+const TOLERATED_ROLLBACK_DEPTH: u32 = 2;
+
+/// Collects the ping and rollback depths of a round to recommend the frame delay for the next round.
+/// GGRS can not change the frame delay of a running session, so recommendations are meant to be applied between rounds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameDelayTuner {
+    ping_total: u128,
+    ping_samples: u64,
+    rollback_depth_total: u64,
+    rollbacks: u64,
+}
+
+impl FrameDelayTuner {
+    /// Creates a [FrameDelayTuner] without samples.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the ping to a remote player in milliseconds.
+    pub fn record_ping(&mut self, ping_ms: u128) {
+        self.ping_total += ping_ms;
+        self.ping_samples += 1;
+    }
+
+    /// Records the depth of a rollback.
+    pub fn record_rollback(&mut self, depth: u32) {
+        self.rollback_depth_total += depth as u64;
+        self.rollbacks += 1;
+    }
+
+    /// Returns the frame delay to use for the next round, given the frame delay and frames per second of the current round.
+    /// Without any ping samples the current frame delay is returned.
+    pub fn recommend(&self, current_delay: u32, fps: u32) -> u32 {
+        if self.ping_samples == 0 {
+            return current_delay;
+        }
+        let frame_ms = 1000.0 / fps.max(1) as f64;
+        let average_ping = self.ping_total as f64 / self.ping_samples as f64;
+        //Remote inputs arrive half a round trip late, the delay covers what rollbacks should not
+        let frames_in_flight = (average_ping / 2.0 / frame_ms).ceil() as u32;
+        let from_ping = frames_in_flight.saturating_sub(TOLERATED_ROLLBACK_DEPTH);
+        //Rollbacks deeper than the ping suggests are caused by jitter, which needs a bit more delay
+        let from_rollbacks = match self.rollbacks {
+            0 => 0,
+            rollbacks => {
+                let average_depth = (self.rollback_depth_total as f64 / rollbacks as f64).round();
+                (current_delay + average_depth as u32).saturating_sub(TOLERATED_ROLLBACK_DEPTH)
+            }
+        };
+        from_ping
+            .max(from_rollbacks)
+            .min(MAX_RECOMMENDED_FRAME_DELAY)
+    }
+
+    /// Discards all samples, call this when a new round starts.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_current_delay_without_ping_samples() {
+        let mut tuner = FrameDelayTuner::new();
+        tuner.record_rollback(6);
+        assert_eq!(tuner.recommend(3, DEFAULT_FPS), 3);
+    }
+
+    #[test]
+    fn recommends_more_delay_for_higher_ping() {
+        let mut tuner = FrameDelayTuner::new();
+        tuner.record_ping(20);
+        assert_eq!(tuner.recommend(2, DEFAULT_FPS), 0);
+
+        tuner.reset();
+        //A round trip of 160ms keeps inputs in flight for 5 frames at 60 fps
+        tuner.record_ping(150);
+        tuner.record_ping(170);
+        assert_eq!(tuner.recommend(0, DEFAULT_FPS), 3);
+        assert_eq!(tuner.recommend(0, 30), 1);
+
+        tuner.record_ping(5000);
+        assert_eq!(tuner.recommend(0, DEFAULT_FPS), MAX_RECOMMENDED_FRAME_DELAY);
+    }
+
+    #[test]
+    fn recommends_more_delay_for_deep_rollbacks() {
+        let mut tuner = FrameDelayTuner::new();
+        tuner.record_ping(60);
+        for depth in [5, 6, 7] {
+            tuner.record_rollback(depth);
+        }
+        assert_eq!(tuner.recommend(1, DEFAULT_FPS), 5);
+
+        tuner.reset();
+        tuner.record_ping(60);
+        tuner.record_rollback(1);
+        assert_eq!(tuner.recommend(1, DEFAULT_FPS), 0);
+    }
+}
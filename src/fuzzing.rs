@@ -0,0 +1,167 @@
+//! Driving a [SyncTestSession] with seeded random inputs to find nondeterminism without playing the game.
+
+use crate::rollback_callbacks::{self, InputConfirmation, RollbackCallbacks};
+use crate::session_metrics::SessionMetrics;
+use crate::xorshift::Xorshift;
+use ggrs::{Frame, GGRSError, SyncTestSession, NULL_FRAME};
+
+/// Generates random inputs for every player, the same seed always generates the same inputs.
+#[derive(Debug, Clone)]
+pub struct FuzzInputs {
+    rng: Xorshift,
+    num_players: u32,
+    input_size: usize,
+}
+
+impl FuzzInputs {
+    /// Creates a generator of inputs with the given size for the given amount of players.
+    pub fn new(seed: u64, num_players: u32, input_size: usize) -> Self {
+        FuzzInputs {
+            rng: Xorshift::new(seed),
+            num_players,
+            input_size,
+        }
+    }
+
+    /// Returns the inputs of all players for the next frame, ordered by handle.
+    pub fn next_inputs(&mut self) -> Vec<Vec<u8>> {
+        (0..self.num_players)
+            .map(|_| self.rng.bytes(self.input_size))
+            .collect()
+    }
+}
+
+/// The outcome of a call to [run_fuzz()].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzReport {
+    /// The seed the inputs were generated from.
+    pub seed: u64,
+    /// The amount of frames that were advanced before the run ended.
+    pub frames_advanced: u32,
+    /// The error that ended the run early, if any.
+    pub error: Option<GGRSError>,
+}
+
+impl FuzzReport {
+    /// Returns the frame of which the checksums mismatched, or [NULL_FRAME] if no mismatch was found.
+    pub fn mismatch_frame(&self) -> Frame {
+        match self.error {
+            Some(GGRSError::MismatchedChecksum { frame }) => frame,
+            _ => NULL_FRAME,
+        }
+    }
+}
+
+/// Advances the session by the given amount of frames with random inputs generated from the seed, stopping at the first error.
+pub fn run_fuzz<C: RollbackCallbacks>(
+    sess: &mut SyncTestSession,
+    callbacks: &mut C,
+    frames: u32,
+    seed: u64,
+    metrics: &mut SessionMetrics,
+) -> FuzzReport {
+    let mut inputs = FuzzInputs::new(seed, sess.num_players(), sess.input_size());
+    let mut report = FuzzReport {
+        seed,
+        frames_advanced: 0,
+        error: None,
+    };
+    while report.frames_advanced < frames {
+        match sess.advance_frame(&inputs.next_inputs()) {
+            Ok(requests) => {
+                rollback_callbacks::handle_requests(
+                    callbacks,
+                    requests,
+                    InputConfirmation::ALL_CONFIRMED,
+                    metrics,
+                );
+                report.frames_advanced += 1;
+            }
+            Err(e) => {
+                report.error = Some(e);
+                break;
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollback_callbacks::InputStatus;
+    use ggrs::GameInput;
+
+    /// A game of which the state is a hash of all inputs, which can be made to depend on how often the game was advanced.
+    #[derive(Default)]
+    struct HashGame {
+        state: u64,
+        advances: u64,
+        nondeterministic: bool,
+    }
+
+    impl RollbackCallbacks for HashGame {
+        fn save_game_state(&mut self, _frame: Frame) -> (Vec<u8>, u64) {
+            (self.state.to_le_bytes().to_vec(), self.state)
+        }
+
+        fn load_game_state(&mut self, _frame: Frame, state: Vec<u8>, _checksum: u64) {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&state);
+            self.state = u64::from_le_bytes(bytes);
+        }
+
+        fn advance_frame(&mut self, inputs: Vec<GameInput>, _: &[InputStatus], _: bool) {
+            self.advances += 1;
+            for input in inputs {
+                for byte in &input.buffer[..input.size] {
+                    self.state = (self.state ^ *byte as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+            //Re-simulated frames are advanced again, so this breaks after the first rollback
+            if self.nondeterministic && self.advances > 10 {
+                self.state ^= self.advances;
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_generates_the_same_inputs() {
+        let mut first = FuzzInputs::new(7, 2, 4);
+        let mut second = FuzzInputs::new(7, 2, 4);
+        let mut other = FuzzInputs::new(8, 2, 4);
+        let inputs = first.next_inputs();
+        assert_eq!(inputs.len(), 2);
+        assert!(inputs.iter().all(|input| input.len() == 4));
+        assert_eq!(inputs, second.next_inputs());
+        assert_ne!(inputs, other.next_inputs());
+    }
+
+    #[test]
+    fn runs_every_frame_of_a_deterministic_game() {
+        let mut sess = SyncTestSession::new(2, 4, 8, 2).unwrap();
+        let report = run_fuzz(
+            &mut sess,
+            &mut HashGame::default(),
+            200,
+            42,
+            &mut SessionMetrics::new(),
+        );
+        assert_eq!(report.error, None);
+        assert_eq!(report.frames_advanced, 200);
+        assert_eq!(report.mismatch_frame(), NULL_FRAME);
+    }
+
+    #[test]
+    fn stops_at_the_first_mismatch_of_a_nondeterministic_game() {
+        let mut sess = SyncTestSession::new(2, 4, 8, 2).unwrap();
+        let mut game = HashGame {
+            nondeterministic: true,
+            ..HashGame::default()
+        };
+        let report = run_fuzz(&mut sess, &mut game, 200, 42, &mut SessionMetrics::new());
+        assert!(report.frames_advanced < 200);
+        assert_ne!(report.mismatch_frame(), NULL_FRAME);
+        assert_eq!(report.seed, 42);
+    }
+}
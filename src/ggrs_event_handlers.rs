@@ -0,0 +1,211 @@
+use crate::desync_detection::Desync;
+use crate::*;
+use ggrs::{GGRSEvent, NetworkStats, PlayerHandle};
+
+/// The maximum amount of events that are kept around for `get_events()`, older events will be discarded.
+pub const MAX_EVENT_QUEUE_SIZE: usize = 100;
+
+pub fn register_event_signals<C: NativeClass>(builder: &ClassBuilder<C>) {
+    builder.add_signal(Signal {
+        name: SIGNAL_WAIT_RECOMMENDATION,
+        args: &[int_argument("skip_frames")],
+    });
+    builder.add_signal(Signal {
+        name: SIGNAL_NETWORK_INTERRUPTED,
+        args: &[int_argument("handle"), int_argument("timeout_ms")],
+    });
+    builder.add_signal(Signal {
+        name: SIGNAL_NETWORK_RESUMED,
+        args: &[int_argument("handle")],
+    });
+    builder.add_signal(Signal {
+        name: SIGNAL_DISCONNECTED,
+        args: &[int_argument("handle")],
+    });
+    builder.add_signal(Signal {
+        name: SIGNAL_SYNCHRONIZING,
+        args: &[
+            int_argument("handle"),
+            int_argument("count"),
+            int_argument("total"),
+        ],
+    });
+    builder.add_signal(Signal {
+        name: SIGNAL_SYNCHRONIZED,
+        args: &[int_argument("handle")],
+    });
+}
+
+pub fn emit_event_signal(owner: &Node, event: GGRSEvent) {
+    //Signals are emitted deferred so that connected methods can safely call back into the session
+    let (name, args) = match event {
+        GGRSEvent::WaitRecommendation { skip_frames } => {
+            (SIGNAL_WAIT_RECOMMENDATION, vec![skip_frames.to_variant()])
+        }
+        GGRSEvent::NetworkInterrupted {
+            player_handle,
+            disconnect_timeout,
+        } => (
+            SIGNAL_NETWORK_INTERRUPTED,
+            vec![
+                player_handle.to_variant(),
+                (disconnect_timeout as u64).to_variant(),
+            ],
+        ),
+        GGRSEvent::NetworkResumed { player_handle } => {
+            (SIGNAL_NETWORK_RESUMED, vec![player_handle.to_variant()])
+        }
+        GGRSEvent::Disconnected { player_handle } => {
+            (SIGNAL_DISCONNECTED, vec![player_handle.to_variant()])
+        }
+        GGRSEvent::Synchronizing {
+            player_handle,
+            total,
+            count,
+        } => (
+            SIGNAL_SYNCHRONIZING,
+            vec![
+                player_handle.to_variant(),
+                count.to_variant(),
+                total.to_variant(),
+            ],
+        ),
+        GGRSEvent::Synchronized { player_handle } => {
+            (SIGNAL_SYNCHRONIZED, vec![player_handle.to_variant()])
+        }
+    };
+    let mut varargs = vec![name.to_variant()];
+    varargs.extend(args);
+    unsafe { owner.call_deferred("emit_signal", &varargs) };
+}
+
+pub fn register_network_stats_signal<C: NativeClass>(builder: &ClassBuilder<C>) {
+    builder.add_signal(Signal {
+        name: SIGNAL_NETWORK_STATS_UPDATED,
+        args: &[
+            int_argument("handle"),
+            SignalArgument {
+                name: "stats",
+                default: Variant::new(),
+                export_info: ExportInfo::new(VariantType::Dictionary),
+                usage: PropertyUsage::DEFAULT,
+            },
+        ],
+    });
+}
+
+pub fn emit_network_stats_signal(owner: &Node, handle: PlayerHandle, stats: &NetworkStats) {
+    let varargs = [
+        SIGNAL_NETWORK_STATS_UPDATED.to_variant(),
+        handle.to_variant(),
+        network_stats_to_dictionary(stats).owned_to_variant(),
+    ];
+    unsafe { owner.call_deferred("emit_signal", &varargs) };
+}
+
+pub fn register_desync_signal<C: NativeClass>(builder: &ClassBuilder<C>) {
+    builder.add_signal(Signal {
+        name: SIGNAL_DESYNC_DETECTED,
+        args: &[
+            int_argument("frame"),
+            int_argument("local_checksum"),
+            int_argument("remote_checksum"),
+            int_argument("handle"),
+        ],
+    });
+}
+
+pub fn emit_desync_signal(owner: &Node, desync: &Desync) {
+    //Checksums are passed as their bit pattern, since Godot ints are signed
+    let varargs = [
+        SIGNAL_DESYNC_DETECTED.to_variant(),
+        desync.frame.to_variant(),
+        (desync.local_checksum as i64).to_variant(),
+        (desync.remote_checksum as i64).to_variant(),
+        desync.handle.to_variant(),
+    ];
+    unsafe { owner.call_deferred("emit_signal", &varargs) };
+}
+
+pub fn register_session_closed_signal<C: NativeClass>(builder: &ClassBuilder<C>) {
+    builder.add_signal(Signal {
+        name: SIGNAL_SESSION_CLOSED,
+        args: &[],
+    });
+}
+
+pub fn emit_session_closed_signal(owner: &Node) {
+    unsafe { owner.call_deferred("emit_signal", &[SIGNAL_SESSION_CLOSED.to_variant()]) };
+}
+
+pub fn register_frame_delay_changed_signal<C: NativeClass>(builder: &ClassBuilder<C>) {
+    builder.add_signal(Signal {
+        name: SIGNAL_FRAME_DELAY_CHANGED,
+        args: &[
+            int_argument("handle"),
+            int_argument("old_frame_delay"),
+            int_argument("new_frame_delay"),
+        ],
+    });
+}
+
+pub fn emit_frame_delay_changed_signal(
+    owner: &Node,
+    handle: PlayerHandle,
+    old_frame_delay: u32,
+    new_frame_delay: u32,
+) {
+    let varargs = [
+        SIGNAL_FRAME_DELAY_CHANGED.to_variant(),
+        handle.to_variant(),
+        old_frame_delay.to_variant(),
+        new_frame_delay.to_variant(),
+    ];
+    unsafe { owner.call_deferred("emit_signal", &varargs) };
+}
+
+/// Converts network stats into a `Dictionary` with the same keys as the fields of [NetworkStats].
+pub fn network_stats_to_dictionary(stats: &NetworkStats) -> Dictionary<Unique> {
+    let result = Dictionary::new();
+    result.insert("send_queue_len", stats.send_queue_len as u64);
+    result.insert("ping", stats.ping as u64);
+    result.insert("kbps_sent", stats.kbps_sent as u64);
+    result.insert("local_frames_behind", stats.local_frames_behind);
+    result.insert("remote_frames_behind", stats.remote_frames_behind);
+    result
+}
+
+/// Converts an event into the `(name, data)` tuple returned by `get_events()`.
+pub fn event_to_tuple(event: GGRSEvent) -> (&'static str, Variant) {
+    match event {
+        GGRSEvent::WaitRecommendation { skip_frames } => {
+            ("WaitRecommendation", skip_frames.to_variant())
+        }
+        GGRSEvent::NetworkInterrupted {
+            player_handle,
+            disconnect_timeout,
+        } => (
+            "NetworkInterrupted",
+            (player_handle, disconnect_timeout as u64).to_variant(),
+        ),
+        GGRSEvent::NetworkResumed { player_handle } => {
+            ("NetworkResumed", player_handle.to_variant())
+        }
+        GGRSEvent::Disconnected { player_handle } => ("Disconnected", player_handle.to_variant()),
+        GGRSEvent::Synchronized { player_handle } => ("Synchronized", player_handle.to_variant()),
+        GGRSEvent::Synchronizing {
+            player_handle,
+            total,
+            count,
+        } => ("Synchronizing", (player_handle, total, count).to_variant()),
+    }
+}
+
+fn int_argument(name: &str) -> SignalArgument {
+    SignalArgument {
+        name,
+        default: Variant::from_i64(0),
+        export_info: ExportInfo::new(VariantType::I64),
+        usage: PropertyUsage::DEFAULT,
+    }
+}
@@ -1,61 +1,430 @@
+use crate::desync_detection;
+use crate::input_schema::{InputFieldType, InputSchema, InputSchemaError};
+use crate::mismatch_capture::StateMismatch;
+use crate::rollback_callbacks::{AdvanceFrameCall, InputStatus, RollbackCallbacks};
+use crate::session_metrics::{CallbackTimings, SessionMetrics};
 use crate::*;
-use ggrs::{Frame, GGRSRequest, GameState, GameStateCell};
+use ggrs::{Frame, GameInput};
 
-pub fn handle_requests(callback_node: &Ref<Node>, requests: Vec<GGRSRequest>) {
-    for item in requests {
-        match item {
-            GGRSRequest::AdvanceFrame { inputs } => {
-                ggrs_request_advance_fame(callback_node, inputs)
-            }
-            GGRSRequest::LoadGameState { cell, frame } => {
-                ggrs_request_load_game_state(callback_node, cell, frame)
-            }
-            GGRSRequest::SaveGameState { cell, frame } => {
-                ggrs_request_save_game_state(callback_node, cell, frame);
-            }
+/// The nodes that GGRS requests are forwarded to.
+#[derive(Clone, Copy)]
+pub enum CallbackNodes<'a> {
+    /// A single node which saves and loads the whole game state.
+    Single(&'a Ref<Node>),
+    /// Multiple participants which each save and load their own slice of the game state.
+    /// The slices are concatenated into a single buffer, each slice is prefixed with its length as a little-endian `u32`.
+    Participants(&'a [Ref<Node>]),
+}
+
+impl<'a> CallbackNodes<'a> {
+    /// Picks the rollback participants when any are registered, otherwise falls back to the callback node.
+    pub fn new(
+        callback_node: &'a Option<Ref<Node>>,
+        participants: &'a [Ref<Node>],
+    ) -> Option<Self> {
+        match callback_node {
+            _ if !participants.is_empty() => Some(CallbackNodes::Participants(participants)),
+            Some(node) => Some(CallbackNodes::Single(node)),
+            None => None,
+        }
+    }
+
+    fn nodes(self) -> &'a [Ref<Node>] {
+        match self {
+            CallbackNodes::Single(node) => std::slice::from_ref(node),
+            CallbackNodes::Participants(nodes) => nodes,
         }
     }
 }
 
-pub fn ggrs_request_advance_fame(callback_node: &Ref<Node>, inputs: Vec<ggrs::GameInput>) {
+/// Forwards GGRS requests to the callback functions of Godot nodes.
+pub struct GodotCallbacks<'a> {
+    callback_nodes: CallbackNodes<'a>,
+    input_schema: Option<&'a InputSchema>,
+    resimulation_flag: bool,
+}
+
+impl<'a> GodotCallbacks<'a> {
+    /// Creates [GodotCallbacks] which pass inputs as a `Dictionary` when an input schema is given.
+    /// When `resimulation_flag` is true, the advance callback receives whether the frame is re-simulated as a second argument.
+    pub fn new(
+        callback_nodes: CallbackNodes<'a>,
+        input_schema: Option<&'a InputSchema>,
+        resimulation_flag: bool,
+    ) -> Self {
+        GodotCallbacks {
+            callback_nodes,
+            input_schema,
+            resimulation_flag,
+        }
+    }
+}
+
+impl RollbackCallbacks for GodotCallbacks<'_> {
+    fn save_game_state(&mut self, frame: Frame) -> (Vec<u8>, u64) {
+        ggrs_request_save_game_state(self.callback_nodes, frame)
+    }
+
+    fn load_game_state(&mut self, frame: Frame, state: Vec<u8>, checksum: u64) {
+        ggrs_request_load_game_state(self.callback_nodes, frame, state, checksum)
+    }
+
+    fn advance_frame(
+        &mut self,
+        inputs: Vec<GameInput>,
+        statuses: &[InputStatus],
+        resimulating: bool,
+    ) {
+        ggrs_request_advance_fame(
+            self.callback_nodes,
+            inputs,
+            statuses,
+            resimulating,
+            self.resimulation_flag,
+            self.input_schema,
+        )
+    }
+}
+
+/// Converts [SessionMetrics] into the `Dictionary` returned by `get_session_metrics()`, all times are in microseconds.
+pub fn session_metrics_to_dictionary(metrics: &SessionMetrics) -> Dictionary<Unique> {
+    let result = Dictionary::new();
+    result.insert("load_count", metrics.load.count);
+    result.insert("save_count", metrics.save.count);
+    result.insert("advance_count", metrics.advance.count);
+    result.insert("resimulated_frames", metrics.resimulated_frames());
+    result.insert("max_rollback_depth", metrics.max_rollback_depth());
+    let rollback_depths = Dictionary::new();
+    for (depth, count) in &metrics.rollback_depths {
+        rollback_depths.insert(*depth, *count);
+    }
+    result.insert("rollback_depths", rollback_depths);
+    insert_callback_timings(&result, "advance", &metrics.advance);
+    insert_callback_timings(&result, "load", &metrics.load);
+    insert_callback_timings(&result, "save", &metrics.save);
+    result
+}
+
+fn insert_callback_timings(result: &Dictionary<Unique>, name: &str, timings: &CallbackTimings) {
+    result.insert(
+        format!("average_{}_usec", name),
+        timings.average().as_micros() as u64,
+    );
+    result.insert(
+        format!("peak_{}_usec", name),
+        timings.peak.as_micros() as u64,
+    );
+}
+
+pub fn ggrs_request_advance_fame(
+    callback_nodes: CallbackNodes,
+    inputs: Vec<GameInput>,
+    statuses: &[InputStatus],
+    resimulating: bool,
+    resimulation_flag: bool,
+    input_schema: Option<&InputSchema>,
+) {
     //Parse parameter inputs in a way that godot can handle then call the callback method
-    let node = unsafe { callback_node.assume_safe() };
     let mut godot_array: Vec<Variant> = Vec::new();
-    for i in inputs {
-        let result = (
-            i.frame,
-            i.size,
-            u32::from_be_bytes(
-                i.buffer[..i.size]
-                    .try_into()
-                    .expect("Slice size is too big or too small to convert into u32"),
-            ),
-        )
-            .to_variant();
+    for (i, status) in inputs.iter().zip(statuses) {
+        let input = match input_schema {
+            Some(schema) => input_to_dictionary(schema, &i.buffer[..i.size]).owned_to_variant(),
+            None => input_to_variant(i),
+        };
+        let result = (i.frame, i.size, input, status.as_str()).to_variant();
         godot_array.push(result);
     }
-    unsafe { node.call(CALLBACK_FUNC_ADVANCE_FRAME, &[godot_array.to_variant()]) };
+    let godot_array = godot_array.to_variant();
+    let flag = resimulating.to_variant();
+    for callback_node in callback_nodes.nodes() {
+        let node = unsafe { callback_node.assume_safe() };
+        let call = AdvanceFrameCall::new(
+            resimulating,
+            resimulating && node.has_method(CALLBACK_FUNC_ADVANCE_FRAME_RESIM),
+            resimulation_flag,
+        );
+        if call.pass_resimulation_flag {
+            //The flag is only passed when it was enabled, so callbacks that declare a single parameter keep working
+            unsafe { node.call(call.method, &[godot_array.clone(), flag.clone()]) };
+        } else {
+            unsafe { node.call(call.method, &[godot_array.clone()]) };
+        }
+    }
 }
 
-pub fn ggrs_request_load_game_state(callback_node: &Ref<Node>, cell: GameStateCell, _frame: Frame) {
-    //Unpack the cell and have over it's values to godot so it can handle it.
-    let node = unsafe { callback_node.assume_safe() };
-    let game_state = cell.load();
-    let frame = game_state.frame.to_variant();
-    let buffer = ByteArray::from_vec(game_state.data.unwrap_or_default()).to_variant();
-    let checksum = game_state.checksum.to_variant();
-    unsafe { node.call(CALLBACK_FUNC_LOAD_GAME_STATE, &[frame, buffer, checksum]) };
+pub fn ggrs_request_load_game_state(
+    callback_nodes: CallbackNodes,
+    frame: Frame,
+    state: Vec<u8>,
+    checksum: u64,
+) {
+    //Hand the saved state over to godot so it can handle it.
+    let frame = frame.to_variant();
+    let checksum = checksum.to_variant();
+    match callback_nodes {
+        CallbackNodes::Single(callback_node) => {
+            let buffer = ByteArray::from_vec(state).to_variant();
+            let node = unsafe { callback_node.assume_safe() };
+            unsafe { node.call(CALLBACK_FUNC_LOAD_GAME_STATE, &[frame, buffer, checksum]) };
+        }
+        CallbackNodes::Participants(participants) => {
+            let slices = split_participant_states(&state, participants.len());
+            for (callback_node, slice) in participants.iter().zip(slices) {
+                let buffer = ByteArray::from_vec(slice).to_variant();
+                let node = unsafe { callback_node.assume_safe() };
+                unsafe {
+                    node.call(
+                        CALLBACK_FUNC_LOAD_GAME_STATE,
+                        &[frame.clone(), buffer, checksum.clone()],
+                    )
+                };
+            }
+        }
+    }
 }
 
-pub fn ggrs_request_save_game_state(callback_node: &Ref<Node>, cell: GameStateCell, frame: Frame) {
-    //Store current cell for later use
+/// Calls the save callback of all callback nodes and returns the saved state and its checksum.
+/// The states of participants are concatenated and the checksum is calculated from the checksums of all participants.
+pub fn ggrs_request_save_game_state(callback_nodes: CallbackNodes, frame: Frame) -> (Vec<u8>, u64) {
+    match callback_nodes {
+        CallbackNodes::Single(callback_node) => save_node_state(callback_node, frame),
+        CallbackNodes::Participants(participants) => {
+            let (slices, checksums): (Vec<_>, Vec<_>) = participants
+                .iter()
+                .map(|callback_node| save_node_state(callback_node, frame))
+                .unzip();
+            let checksum_bytes = checksums
+                .iter()
+                .flat_map(|checksum| checksum.to_le_bytes())
+                .collect::<Vec<_>>();
+            (
+                concat_participant_states(&slices),
+                desync_detection::checksum(&checksum_bytes),
+            )
+        }
+    }
+}
+
+/// Calls the save callback of a node and returns the saved state and its checksum.
+/// The callback can either return a `PoolByteArray`, in which case the checksum is calculated from the state,
+/// or an `Array` containing the `PoolByteArray` and an int which is used as the checksum.
+/// A checksum that is not an int is reported, and the checksum is calculated from the state instead.
+fn save_node_state(callback_node: &Ref<Node>, frame: Frame) -> (Vec<u8>, u64) {
     let node = unsafe { callback_node.assume_safe() };
     let state: Variant = unsafe { node.call(CALLBACK_FUNC_SAVE_GAME_STATE, &[frame.to_variant()]) };
-    let state_bytes = ByteArray::from_variant(&state).unwrap_or_default();
-    let mut state_bytes_vec = Vec::new();
-    for i in 0..state_bytes.len() {
-        state_bytes_vec.push(state_bytes.get(i));
+    match VariantArray::from_variant(&state) {
+        Ok(array) if array.len() == 2 => {
+            let state_bytes = ByteArray::from_variant(&array.get(0)).unwrap_or_default();
+            let state_bytes = state_bytes.read().to_vec();
+            let checksum = match array.get(1).try_to_i64() {
+                Some(checksum) => checksum as u64,
+                None => {
+                    godot_error!(
+                        "The checksum returned by {} of {} is not an int, the checksum is calculated from the state instead.",
+                        CALLBACK_FUNC_SAVE_GAME_STATE,
+                        node.name()
+                    );
+                    desync_detection::checksum(&state_bytes)
+                }
+            };
+            (state_bytes, checksum)
+        }
+        _ => {
+            let state_bytes = ByteArray::from_variant(&state).unwrap_or_default();
+            let state_bytes = state_bytes.read().to_vec();
+            let checksum = desync_detection::checksum(&state_bytes);
+            (state_bytes, checksum)
+        }
+    }
+}
+
+/// Calls the describe callback of every callback node that implements it with the original and the re-simulated state, and returns a line for every difference.
+/// The callback should return a `Dictionary` mapping field names to values, so differences can be reported per field. Any other value is compared as a whole.
+/// Participants only receive their own slice of both states.
+pub fn describe_state_mismatch(
+    callback_nodes: CallbackNodes,
+    mismatch: &StateMismatch,
+) -> Vec<String> {
+    let nodes = callback_nodes.nodes();
+    let (original_states, resimulated_states) = match callback_nodes {
+        CallbackNodes::Single(_) => (
+            vec![mismatch.original_state.clone()],
+            vec![mismatch.resimulated_state.clone()],
+        ),
+        CallbackNodes::Participants(participants) => (
+            split_participant_states(&mismatch.original_state, participants.len()),
+            split_participant_states(&mismatch.resimulated_state, participants.len()),
+        ),
+    };
+    let mut lines = Vec::new();
+    for ((callback_node, original_state), resimulated_state) in
+        nodes.iter().zip(original_states).zip(resimulated_states)
+    {
+        let node = unsafe { callback_node.assume_safe() };
+        if !node.has_method(CALLBACK_FUNC_DESCRIBE_STATE) {
+            continue;
+        }
+        let describe = |state: Vec<u8>| unsafe {
+            node.call(
+                CALLBACK_FUNC_DESCRIBE_STATE,
+                &[ByteArray::from_vec(state).to_variant()],
+            )
+        };
+        let differences =
+            diff_descriptions(&describe(original_state), &describe(resimulated_state));
+        lines.extend(
+            differences
+                .into_iter()
+                .map(|difference| format!("{}: {}", node.name(), difference)),
+        );
+    }
+    lines
+}
+
+/// Compares two descriptions returned by the describe callback, `Dictionary` descriptions are compared per field.
+fn diff_descriptions(original: &Variant, resimulated: &Variant) -> Vec<String> {
+    match (
+        Dictionary::from_variant(original),
+        Dictionary::from_variant(resimulated),
+    ) {
+        (Ok(original), Ok(resimulated)) => {
+            let mut fields = original.keys().iter().collect::<Vec<_>>();
+            fields.extend(
+                resimulated
+                    .keys()
+                    .iter()
+                    .filter(|key| !original.contains(key)),
+            );
+            fields
+                .into_iter()
+                .filter_map(|field| {
+                    let (before, after) = (original.get(&field), resimulated.get(&field));
+                    (before != after).then(|| {
+                        format!(
+                            "{} {}",
+                            field.to_godot_string(),
+                            describe_change(&before, &after)
+                        )
+                    })
+                })
+                .collect()
+        }
+        _ if original != resimulated => vec![describe_change(original, resimulated)],
+        _ => Vec::new(),
+    }
+}
+
+fn describe_change(original: &Variant, resimulated: &Variant) -> String {
+    format!(
+        "was {} but is {} after re-simulating",
+        original.to_godot_string(),
+        resimulated.to_godot_string()
+    )
+}
+
+/// Concatenates the states of all participants into a single buffer, prefixing each state with its length.
+pub fn concat_participant_states(slices: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = Vec::new();
+    for slice in slices {
+        result.extend_from_slice(&(slice.len() as u32).to_le_bytes());
+        result.extend_from_slice(slice);
+    }
+    result
+}
+
+/// Splits a buffer created by [concat_participant_states()] back into the states of `count` participants.
+/// Participants without a slice in the buffer receive an empty state.
+pub fn split_participant_states(buffer: &[u8], count: usize) -> Vec<Vec<u8>> {
+    let mut result = Vec::with_capacity(count);
+    let mut offset = 0;
+    for _ in 0..count {
+        let slice = buffer
+            .get(offset..offset + 4)
+            .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize)
+            .and_then(|len| buffer.get(offset + 4..offset + 4 + len));
+        match slice {
+            Some(slice) => {
+                offset += 4 + slice.len();
+                result.push(slice.to_vec());
+            }
+            None => {
+                offset = buffer.len();
+                result.push(Vec::new());
+            }
+        }
+    }
+    result
+}
+
+/// Converts the buffer of a [GameInput] into a variant.
+/// Inputs with the size of a `u32` are passed as an int, all other sizes are passed as a `PoolByteArray`.
+fn input_to_variant(input: &GameInput) -> Variant {
+    match <[u8; DEFAULT_INPUT_SIZE]>::try_from(&input.buffer[..input.size]) {
+        Ok(bytes) => u32::from_be_bytes(bytes).to_variant(),
+        Err(_) => ByteArray::from_slice(&input.buffer[..input.size]).to_variant(),
+    }
+}
+
+/// Creates an [InputSchema] from a Godot `Dictionary` which maps field names to field types, for example `{"buttons": "bits(12)", "stick_x": "i8"}`.
+/// Fields are packed in the order they were inserted into the `Dictionary`.
+pub fn dictionary_to_input_schema(
+    schema: &Dictionary<Shared>,
+) -> Result<InputSchema, InputSchemaError> {
+    let mut result = InputSchema::new();
+    for (name, field_type) in schema.iter() {
+        let field_type = InputFieldType::parse(&field_type.to_string())?;
+        result.add_field(&name.to_string(), field_type)?;
+    }
+    if result.fields().is_empty() {
+        return Err(InputSchemaError::Empty);
+    }
+    Ok(result)
+}
+
+/// Packs a Godot `Dictionary` into an input buffer using the given [InputSchema].
+/// Fields that are missing from the `Dictionary` are packed as zero, keys that are not part of the schema and values of the wrong type are errors.
+pub fn dictionary_to_input(
+    schema: &InputSchema,
+    input: &Dictionary<Shared>,
+) -> Result<Vec<u8>, InputSchemaError> {
+    for key in input.keys().iter() {
+        let key = key.to_string();
+        if !schema.fields().iter().any(|field| field.name == key) {
+            return Err(InputSchemaError::UnknownField(key));
+        }
+    }
+    let mut values = Vec::new();
+    for field in schema.fields() {
+        let value = input.get(field.name.as_str());
+        let value = match (field.field_type, value.get_type()) {
+            (_, VariantType::Nil) => 0,
+            (InputFieldType::Bool, VariantType::Bool) => {
+                value.try_to_bool().unwrap_or_default() as i64
+            }
+            (InputFieldType::Bool, _) => return Err(wrong_value_type(field.name.as_str(), "bool")),
+            (_, VariantType::I64) => value.try_to_i64().unwrap_or_default(),
+            (_, _) => return Err(wrong_value_type(field.name.as_str(), "int")),
+        };
+        values.push(value);
+    }
+    schema.pack(&values)
+}
+
+fn wrong_value_type(field: &str, expected: &str) -> InputSchemaError {
+    InputSchemaError::WrongValueType {
+        field: field.to_owned(),
+        expected: expected.to_owned(),
+    }
+}
+
+/// Unpacks an input buffer into a Godot `Dictionary` using the given [InputSchema].
+pub fn input_to_dictionary(schema: &InputSchema, buffer: &[u8]) -> Dictionary<Unique> {
+    let result = Dictionary::new();
+    for (field, value) in schema.fields().iter().zip(schema.unpack(buffer)) {
+        match field.field_type {
+            InputFieldType::Bool => result.insert(field.name.as_str(), value != 0),
+            _ => result.insert(field.name.as_str(), value),
+        }
     }
-    let result = GameState::new(frame, Some(state_bytes_vec));
-    cell.save(result);
+    result
 }
@@ -0,0 +1,126 @@
+use crate::address;
+use crate::error::GodotGGRSError;
+use crate::transport::{QueuedTransport, Transport, UdpTransport};
+use crate::*;
+use ggrs::GGRSError;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Carries the datagrams of a session over a Godot node, which makes it possible to use relays, WebRTC or an existing `NetworkedMultiplayerENet` connection.
+/// The node needs a [TRANSPORT_FUNC_SEND_TO] and a [TRANSPORT_FUNC_POLL] function, peers are identified by any string the node understands.
+pub struct GodotTransport {
+    node: Ref<Node>,
+    queue: QueuedTransport,
+}
+
+impl GodotTransport {
+    /// Creates a transport that sends and receives through the given node.
+    pub fn new(node: Ref<Node>) -> Self {
+        GodotTransport {
+            node,
+            queue: QueuedTransport::new(),
+        }
+    }
+
+    /// Returns the queue that should be handed to the socket of the session.
+    pub fn queue(&self) -> QueuedTransport {
+        self.queue.clone()
+    }
+
+    /// Polls the node and queues the received datagrams for the session, call this before the session receives its messages.
+    /// The node should return an `Array` of `[address, PoolByteArray]` pairs, anything else is ignored.
+    pub fn receive(&self) {
+        let node = unsafe { self.node.assume_safe() };
+        let received = unsafe { node.call(TRANSPORT_FUNC_POLL, &[]) };
+        let received = match VariantArray::from_variant(&received) {
+            Ok(received) => received,
+            Err(_) => return,
+        };
+        for pair in received.iter() {
+            let pair = match VariantArray::from_variant(&pair) {
+                Ok(pair) if pair.len() == 2 => pair,
+                _ => continue,
+            };
+            if let (Some(addr), Ok(datagram)) = (
+                pair.get(0).try_to_string(),
+                ByteArray::from_variant(&pair.get(1)),
+            ) {
+                self.queue.push_incoming(addr, datagram.read().to_vec());
+            }
+        }
+    }
+
+    /// Sends the datagrams that were queued by the session through the node, call this after the session sent its messages.
+    pub fn send(&self) {
+        let node = unsafe { self.node.assume_safe() };
+        for (addr, datagram) in self.queue.take_outgoing() {
+            let datagram = ByteArray::from_vec(datagram).to_variant();
+            unsafe { node.call(TRANSPORT_FUNC_SEND_TO, &[addr.to_variant(), datagram]) };
+        }
+    }
+}
+
+/// Where a session sends its messages, kept so the same socket can be opened again for a rematch.
+#[derive(Clone)]
+pub enum SessionSocket {
+    /// A UDP socket bound to the given local address.
+    Udp(SocketAddr),
+    /// A transport node, see [GodotTransport].
+    Transport(Ref<Node>),
+}
+
+/// The transport of a session that has been opened from a [SessionSocket].
+pub struct OpenedSocket {
+    /// The transport to hand to the socket of the session.
+    pub transport: Arc<dyn Transport>,
+    /// The transport node that has to be pumped by the session, if any.
+    pub godot_transport: Option<GodotTransport>,
+    /// The address the UDP socket is bound to, if any.
+    pub local_addr: Option<SocketAddr>,
+    /// The socket to open for a rematch, which is bound to the port that was picked when binding to port `0`.
+    pub socket: SessionSocket,
+}
+
+impl SessionSocket {
+    /// Binds the UDP socket or wraps the transport node.
+    pub fn open(&self) -> Result<OpenedSocket, GodotGGRSError> {
+        match self {
+            SessionSocket::Udp(local_addr) => {
+                let udp_transport = UdpTransport::bind(*local_addr)
+                    .map_err(|_| GodotGGRSError::from(GGRSError::SocketCreationFailed))?;
+                let local_addr = udp_transport.local_addr().ok();
+                Ok(OpenedSocket {
+                    transport: Arc::new(udp_transport),
+                    godot_transport: None,
+                    local_addr,
+                    socket: local_addr.map_or_else(|| self.clone(), SessionSocket::Udp),
+                })
+            }
+            SessionSocket::Transport(node) => {
+                let godot_transport = GodotTransport::new(node.clone());
+                Ok(OpenedSocket {
+                    transport: Arc::new(godot_transport.queue()),
+                    godot_transport: Some(godot_transport),
+                    local_addr: None,
+                    socket: self.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Parses the local address given when creating a session, which is either a port as an int or a bind address as a String.
+/// Any other type, like a float or `null`, is reported as a bad address instead of being converted.
+pub fn parse_local_address(local_address: &Variant) -> Result<SocketAddr, GodotGGRSError> {
+    let local_addr = match local_address.get_type() {
+        VariantType::I64 => address::bind_addr_from_port(local_address.to_i64()),
+        VariantType::GodotString => address::parse_bind_addr(&local_address.to_string()),
+        _ => {
+            return Err(GodotGGRSError::BadAddress(format!(
+                "The local address must be a port or a String, but \"{}\" was given.",
+                local_address.to_godot_string()
+            )))
+        }
+    }?;
+    Ok(local_addr)
+}
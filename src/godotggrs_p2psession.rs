@@ -1,15 +1,226 @@
+use crate::address::{self, AddressPreference};
+use crate::desync_detection::DesyncDetector;
+use crate::error::{self, GodotGGRSError};
+use crate::frame_delay_tuning::{self, FrameDelayTuner};
+use crate::ggrs_request_handlers::GodotCallbacks;
+use crate::godot_transport::{self, GodotTransport, SessionSocket};
+use crate::input_schema::InputSchema;
+use crate::network_conditioner::{self, NetworkConditioner, SharedNetworkConditions};
+use crate::rematch::{self, RematchError, RematchSession};
+use crate::replay::{ReplayHeader, ReplayPlayer, ReplayRecorder};
+use crate::rollback_callbacks::{self, InputConfirmation};
+use crate::session_metrics::SessionMetrics;
+use crate::transport;
+use crate::wrapper_socket::{ChecksumChannel, WrapperSocket};
 use crate::*;
-use gdnative::core_types::ToVariant;
-use ggrs::{Frame, GGRSEvent, P2PSession, PlayerHandle, PlayerType, SessionState};
+use gdnative::api::ProjectSettings;
+use ggrs::{Frame, GGRSError, GGRSEvent, P2PSession, PlayerHandle, PlayerType, SessionState};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::SocketAddr;
 use std::option::*;
+use std::time::{Duration, Instant};
 
 /// A Godot implementation of [`P2PSession`]
+/// # Signals
+/// All [GGRSEvent]s are emitted as signals during [Self::poll_remote_clients()] and [Self::advance_frame()]:
+/// - [SIGNAL_WAIT_RECOMMENDATION]
+/// - [SIGNAL_NETWORK_INTERRUPTED]
+/// - [SIGNAL_NETWORK_RESUMED]
+/// - [SIGNAL_DISCONNECTED]
+/// - [SIGNAL_SYNCHRONIZING]
+/// - [SIGNAL_SYNCHRONIZED]
+///
+/// When enabled through [Self::set_network_stats_interval()], [SIGNAL_NETWORK_STATS_UPDATED] is periodically emitted for every remote player and spectator.
+///
+/// The checksums of confirmed frames are exchanged with remote players, [SIGNAL_DESYNC_DETECTED] is emitted when they differ.
+///
+/// When adaptive frame delay is enabled through [Self::set_adaptive_frame_delay()], [SIGNAL_FRAME_DELAY_CHANGED] is emitted for every local player of which [Self::reset_for_rematch()] changed the frame delay.
+///
+/// [SIGNAL_SESSION_CLOSED] is emitted when the session is closed through [Self::close_session()] or [Self::reset_for_rematch()], or replaced by a new session.
+/// # Compatibility
+/// Every message is sent with a header containing the [protocol version](crate::wrapper_socket::PROTOCOL_VERSION) of GodotGGRS, so the checksums can travel over the same socket as the messages of GGRS.
+/// Peers and spectators running another version, or plain GGRS, can not connect to this session. Messages from such a peer are dropped and reported once as `ERR_INCOMPATIBLE_PEER`.
+/// # Errors
+/// Failing functions print an error and record an error code, which can be retrieved with [Self::get_last_error_code()].
+/// All codes are exposed as integer constants on the node, like `ERR_NO_SESSION` or `ERR_NOT_SYNCHRONIZED`.
 #[derive(NativeClass)]
 #[inherit(Node)]
+#[register_with(Self::register)]
 pub struct GodotGGRSP2PSession {
-    sess: Option<P2PSession>,
+    sess: Option<P2PSession<Vec<u8>, String>>,
     callback_node: Option<Ref<Node>>,
+    rollback_participants: Vec<Ref<Node>>,
+    input_schema: Option<InputSchema>,
+    resimulation_flag: bool,
     next_handle: usize,
+    events: VecDeque<GGRSEvent>,
+    auto_frame_skip: bool,
+    frames_to_skip: u32,
+    last_error: Option<GodotGGRSError>,
+    metrics: SessionMetrics,
+    address_preference: AddressPreference,
+    remote_handles: Vec<PlayerHandle>,
+    network_stats_interval: Option<Duration>,
+    last_network_stats_update: Instant,
+    remote_players: Vec<(PlayerHandle, String)>,
+    checksum_channel: Option<ChecksumChannel>,
+    desync_detector: DesyncDetector,
+    local_handles: Vec<PlayerHandle>,
+    frame_delays: HashMap<PlayerHandle, u32>,
+    recorder: Option<ReplayRecorder<BufWriter<File>>>,
+    network_conditions: SharedNetworkConditions,
+    transport: Option<GodotTransport>,
+    local_addr: Option<SocketAddr>,
+    config: Option<SessionConfig>,
+    adaptive_frame_delay: bool,
+    apply_recommended_frame_delay: bool,
+    frame_delay_tuner: FrameDelayTuner,
+}
+
+/// The status that is returned when advancing a frame, its discriminant is the code returned to Godot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AdvanceFrameStatus {
+    /// The frame has been advanced.
+    Advanced = 0,
+    /// The frame has been skipped to let remote clients catch up.
+    Skipped = 1,
+    /// The session is not running yet.
+    NotRunning = 2,
+    /// The frame could not be advanced because of an error, for example when no session was made.
+    Failed = 3,
+}
+
+/// The names and codes of all [AdvanceFrameStatus]es, these are registered as constants on [GodotGGRSP2PSession].
+const ADVANCE_FRAME_STATUS_CODES: &[(&str, i64)] = &[
+    ("ADVANCE_ADVANCED", AdvanceFrameStatus::Advanced as i64),
+    ("ADVANCE_SKIPPED", AdvanceFrameStatus::Skipped as i64),
+    ("ADVANCE_NOT_RUNNING", AdvanceFrameStatus::NotRunning as i64),
+    ("ADVANCE_FAILED", AdvanceFrameStatus::Failed as i64),
+];
+
+impl AdvanceFrameStatus {
+    fn code(self) -> i64 {
+        self as i64
+    }
+}
+
+/// Everything a session was set up with, so [GodotGGRSP2PSession::reset_for_rematch()] can set it up again.
+/// Frame delays are kept in [GodotGGRSP2PSession::frame_delays], since they are also needed for recordings.
+#[derive(Clone)]
+struct SessionConfig {
+    socket: SessionSocket,
+    num_players: u32,
+    max_pred: usize,
+    input_size: usize,
+    players: Vec<PlayerType<String>>,
+    fps: Option<u32>,
+    disconnect_timeout: Option<Duration>,
+    disconnect_notify_delay: Option<Duration>,
+    sparse_saving: Option<bool>,
+}
+
+/// The config, frame delays and recommended frame delays a session is set up again with by [GodotGGRSP2PSession::reset_for_rematch()].
+#[derive(Clone)]
+struct RematchConfig {
+    session: SessionConfig,
+    frame_delays: HashMap<PlayerHandle, u32>,
+    /// The handle, old frame delay and recommended frame delay of every local player of which the frame delay changes.
+    changed_frame_delays: Vec<(PlayerHandle, u32, u32)>,
+}
+
+/// Resets a [GodotGGRSP2PSession] through [rematch::reset_for_rematch()], the owner is needed to emit signals.
+struct SessionReset<'a> {
+    session: &'a mut GodotGGRSP2PSession,
+    owner: &'a Node,
+}
+
+impl RematchSession for SessionReset<'_> {
+    type Config = RematchConfig;
+
+    fn config(&self) -> Option<RematchConfig> {
+        let session = &*self.session;
+        let config = session.config.clone()?;
+        let mut changed_frame_delays = Vec::new();
+        if session.adaptive_frame_delay && session.apply_recommended_frame_delay {
+            let fps = config.fps.unwrap_or(frame_delay_tuning::DEFAULT_FPS);
+            //Players keep their handles, which are given out in the order the players were added
+            let local_handles = config
+                .players
+                .iter()
+                .enumerate()
+                .filter(|(_, player_type)| matches!(player_type, PlayerType::Local))
+                .map(|(handle, _)| handle);
+            for handle in local_handles {
+                let old_frame_delay = session.frame_delays.get(&handle).copied().unwrap_or(0);
+                let new_frame_delay = session.frame_delay_tuner.recommend(old_frame_delay, fps);
+                if new_frame_delay != old_frame_delay {
+                    changed_frame_delays.push((handle, old_frame_delay, new_frame_delay));
+                }
+            }
+        }
+        Some(RematchConfig {
+            session: config,
+            frame_delays: session.frame_delays.clone(),
+            changed_frame_delays,
+        })
+    }
+
+    fn open(&mut self, config: &RematchConfig) -> bool {
+        let config = &config.session;
+        self.session.open_session(
+            self.owner,
+            config.socket.clone(),
+            config.num_players,
+            config.max_pred,
+            config.input_size,
+        )
+    }
+
+    fn restore(&mut self, config: &RematchConfig) -> bool {
+        let (session, owner) = (&mut *self.session, self.owner);
+        for player_type in &config.session.players {
+            if session.add_player(player_type.clone()) == INVALID_PLAYER_HANDLE {
+                return false;
+            }
+        }
+        let mut frame_delays = config.frame_delays.clone();
+        for (handle, _, new_frame_delay) in &config.changed_frame_delays {
+            frame_delays.insert(*handle, *new_frame_delay);
+        }
+        for (player_handle, frame_delay) in frame_delays {
+            session.set_frame_delay(owner, frame_delay, player_handle);
+        }
+        for (handle, old_frame_delay, new_frame_delay) in &config.changed_frame_delays {
+            ggrs_event_handlers::emit_frame_delay_changed_signal(
+                owner,
+                *handle,
+                *old_frame_delay,
+                *new_frame_delay,
+            );
+        }
+        let config = &config.session;
+        if let Some(fps) = config.fps {
+            session.set_fps(owner, fps);
+        }
+        if let Some(timeout) = config.disconnect_timeout {
+            session.set_disconnect_timeout(owner, timeout.as_secs());
+        }
+        if let Some(delay) = config.disconnect_notify_delay {
+            session.set_disconnect_notify_delay(owner, delay.as_secs());
+        }
+        if let Some(sparse_saving) = config.sparse_saving {
+            session.set_sparse_saving(owner, sparse_saving);
+        }
+        true
+    }
+
+    fn abort(&mut self, config: RematchConfig) {
+        self.session.close(self.owner);
+        self.session.config = Some(config.session);
+        self.session.frame_delays = config.frame_delays;
+    }
 }
 
 impl GodotGGRSP2PSession {
@@ -17,9 +228,44 @@ impl GodotGGRSP2PSession {
         GodotGGRSP2PSession {
             sess: None,
             callback_node: None,
+            rollback_participants: Vec::new(),
+            input_schema: None,
+            resimulation_flag: false,
             next_handle: 0,
+            events: VecDeque::new(),
+            auto_frame_skip: false,
+            frames_to_skip: 0,
+            last_error: None,
+            metrics: SessionMetrics::new(),
+            address_preference: AddressPreference::default(),
+            remote_handles: Vec::new(),
+            network_stats_interval: None,
+            last_network_stats_update: Instant::now(),
+            remote_players: Vec::new(),
+            checksum_channel: None,
+            desync_detector: DesyncDetector::new(),
+            local_handles: Vec::new(),
+            frame_delays: HashMap::new(),
+            recorder: None,
+            network_conditions: SharedNetworkConditions::default(),
+            transport: None,
+            local_addr: None,
+            config: None,
+            adaptive_frame_delay: false,
+            apply_recommended_frame_delay: false,
+            frame_delay_tuner: FrameDelayTuner::new(),
         }
     }
+
+    fn register(builder: &ClassBuilder<Self>) {
+        ggrs_event_handlers::register_event_signals(builder);
+        ggrs_event_handlers::register_network_stats_signal(builder);
+        ggrs_event_handlers::register_desync_signal(builder);
+        ggrs_event_handlers::register_session_closed_signal(builder);
+        ggrs_event_handlers::register_frame_delay_changed_signal(builder);
+        error::register_error_codes(builder);
+        error::register_constants(builder, ADVANCE_FRAME_STATUS_CODES);
+    }
 }
 
 #[methods]
@@ -31,63 +277,174 @@ impl GodotGGRSP2PSession {
     }
 
     /// Creates a [P2PSession],
-    /// call this when you want to start setting up a P2P Session takes the local port, total number of players and max prediction frames as parameters.
+    /// call this when you want to start setting up a P2P Session takes the local port, total number of players, max prediction frames and optionally the input size in bytes as parameters.
     /// # Notes
+    /// - The local port can also be a bind address as a String, like `"192.168.1.5:7070"` to bind to a single interface or `"[::]:7070"` to listen on IPv6. A port given as an int binds on all IPv4 interfaces.
+    /// - When the port is `0` the system picks a free port, which can be retrieved with [Self::get_local_port()].
     /// - Max prediction frames is the maximum number of frames GGRS will roll back. Every gamestate older than this is guaranteed to be correct if the players did not desync.
     /// - This value used to default to `8 frames`, but this has been made adjustable with `GGRS 0.7.0`
+    /// - When the input size is omitted or `0` it defaults to [DEFAULT_INPUT_SIZE], which allows inputs to be passed as an int through [Self::advance_frame()].
+    /// Any other input size requires inputs to be passed as a `PoolByteArray` through [Self::advance_frame_bytes()].
+    /// - When an input schema has been set through [Self::set_input_schema()] and the input size is omitted, the input size of the schema is used.
+    /// - The socket of the session also carries the checksums used for desync detection, so all peers need to use the same version of GodotGGRS. See the compatibility notes of [GodotGGRSP2PSession].
+    /// # Errors
+    /// - Will print an error if the local address is not a port or a socket address, or if the socket could not be bound to it
     #[export]
     pub fn create_new_session(
         &mut self,
-        _owner: &Node,
-        local_port: u16,
+        owner: &Node,
+        local_address: Variant,
         num_players: u32,
         max_pred: usize,
+        #[opt] input_size: usize,
     ) {
-        let input_size: usize = std::mem::size_of::<u32>();
-        match P2PSession::new(num_players, input_size, max_pred, local_port) {
-            Ok(s) => self.sess = Some(s),
-            Err(e) => godot_error!("{}", e),
+        match godot_transport::parse_local_address(&local_address) {
+            Ok(local_addr) => {
+                self.open_session(
+                    owner,
+                    SessionSocket::Udp(local_addr),
+                    num_players,
+                    max_pred,
+                    input_size,
+                );
+            }
+            Err(e) => self.report(e),
         }
     }
 
+    /// Creates a [P2PSession] that sends its messages through a transport node instead of binding its own UDP socket, for example to use relays, WebRTC or an existing `NetworkedMultiplayerENet` connection.
+    /// Takes the transport node, total number of players, max prediction frames and optionally the input size in bytes as parameters, see [Self::create_new_session()].
+    /// # Notes
+    /// - The node needs a [TRANSPORT_FUNC_SEND_TO] function taking the address of a peer and a `PoolByteArray`, and a [TRANSPORT_FUNC_POLL] function returning an `Array` of `[address, PoolByteArray]` pairs.
+    /// - Addresses are opaque strings that are passed to [Self::add_remote_player()] and [Self::add_spectator()] as they are, like a Steam ID or a network peer ID.
+    /// - The node is polled during [Self::poll_remote_clients()] and [Self::advance_frame()], datagrams are sent at the end of those calls.
+    #[export]
+    pub fn create_new_session_with_transport(
+        &mut self,
+        owner: &Node,
+        transport: Ref<Node>,
+        num_players: u32,
+        max_pred: usize,
+        #[opt] input_size: usize,
+    ) {
+        self.open_session(
+            owner,
+            SessionSocket::Transport(transport),
+            num_players,
+            max_pred,
+            input_size,
+        );
+    }
+
     /// Deprecated method to create a [P2PSession]. Use [Self::create_new_session()] instead.
     #[deprecated(since = "0.5.0", note = "please use `create_new_session()` instead")]
     #[export]
     pub fn create_session(&mut self, _owner: &Node, local_port: u16, num_players: u32) {
-        self.create_new_session(_owner, local_port, num_players, 8)
+        self.create_new_session(
+            _owner,
+            local_port.to_variant(),
+            num_players,
+            8,
+            DEFAULT_INPUT_SIZE,
+        )
     }
 
     /// Adds a local player to the session and return the handle.
+    /// Returns [INVALID_PLAYER_HANDLE] if the player could not be added, the reason can be retrieved with [Self::get_last_error()].
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn add_local_player(&mut self, _owner: &Node) -> PlayerHandle {
+    pub fn add_local_player(&mut self, _owner: &Node) -> i64 {
         self.add_player(PlayerType::Local)
     }
 
     /// Adds a remote player to the session and returns the handle.
+    /// Returns [INVALID_PLAYER_HANDLE] if the player could not be added, the reason can be retrieved with [Self::get_last_error()].
     /// # Example
-    /// The following example shows how to format an address string, starting with the IP and ending with the port.
+    /// The following example shows how to format an address string, starting with the IP or hostname and ending with the port.
     /// ```
-    /// p2p.add_remote_player("127.0.0.1:7070")
+    /// var handle = p2p.add_remote_player("myhost.lan:7070")
+    /// if handle == -1:
+    ///     show_lobby_error(p2p.get_last_error())
     /// ```
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
-    /// - Will panic if the address string could not be converted to an [std::net::SocketAddr]
+    /// - Will print an error if the address string could not be resolved to an [std::net::SocketAddr], unless the session was created with [Self::create_new_session_with_transport()]
     #[export]
-    pub fn add_remote_player(&mut self, _owner: &Node, address: String) -> PlayerHandle {
-        let remote_addr: std::net::SocketAddr = address.parse().unwrap();
-        self.add_player(PlayerType::Remote(remote_addr))
+    pub fn add_remote_player(&mut self, _owner: &Node, address: String) -> i64 {
+        match self.peer_address(&address) {
+            Ok(remote_addr) => self.add_player(PlayerType::Remote(remote_addr)),
+            Err(e) => self.player_error(e.into()),
+        }
     }
 
     /// Adds a spectator to the session and returns the handle
+    /// Returns [INVALID_PLAYER_HANDLE] if the spectator could not be added, the reason can be retrieved with [Self::get_last_error()].
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
-    /// - Will panic if the address string could not be converted to an [std::net::SocketAddr]
+    /// - Will print an error if the address string could not be resolved to an [std::net::SocketAddr], unless the session was created with [Self::create_new_session_with_transport()]
+    #[export]
+    pub fn add_spectator(&mut self, _owner: &Node, address: String) -> i64 {
+        match self.peer_address(&address) {
+            Ok(remote_addr) => self.add_player(PlayerType::Spectator(remote_addr)),
+            Err(e) => self.player_error(e.into()),
+        }
+    }
+
+    /// Sets which IP version is preferred when a hostname resolves to multiple addresses, the default is `"ipv4"`.
+    /// Accepts `"ipv4"`, `"ipv6"` or `"any"`, when no address of the preferred version is found the first resolved address is used.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_INVALID_ADDRESS_PREFERENCE] error if the preference is unknown
+    #[export]
+    pub fn set_address_preference(&mut self, _owner: &Node, preference: String) {
+        match AddressPreference::parse(&preference) {
+            Some(p) => self.address_preference = p,
+            None => self.report(GodotGGRSError::BadAddress(
+                ERR_MESSAGE_INVALID_ADDRESS_PREFERENCE.to_owned(),
+            )),
+        }
+    }
+
+    /// Returns the local port the UDP socket of the session is bound to, which is the port the system picked when the session was created with port `0`.
+    /// Returns `0` when the session sends its messages through a transport node.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    #[export]
+    pub fn get_local_port(&mut self, _owner: &Node) -> u16 {
+        match (&self.sess, self.local_addr) {
+            (None, _) => {
+                self.report(GodotGGRSError::NoSession);
+                0
+            }
+            (Some(_), Some(local_addr)) => local_addr.port(),
+            (Some(_), None) => 0,
+        }
+    }
+
+    /// Returns the code of the last error that occurred, or `ERR_OK` if no error occurred since [Self::clear_last_error()].
+    /// The codes are available as constants on the session, like `ERR_NO_SESSION` or `ERR_INVALID_HANDLE`.
+    /// # Example
+    /// ```gdscript
+    /// if p2p.get_last_error_code() == p2p.ERR_BAD_ADDRESS:
+    ///     show_lobby_error(p2p.get_last_error())
+    /// ```
+    #[export]
+    pub fn get_last_error_code(&mut self, _owner: &Node) -> i64 {
+        error::last_error_code(&self.last_error)
+    }
+
+    /// Returns the message of the last error that occurred, or an empty String if no error occurred since [Self::clear_last_error()].
+    #[export]
+    pub fn get_last_error(&mut self, _owner: &Node) -> String {
+        self.last_error
+            .as_ref()
+            .map_or_else(String::new, |e| e.to_string())
+    }
+
+    /// Clears the last error, after which [Self::get_last_error_code()] returns `ERR_OK` until another error occurs.
     #[export]
-    pub fn add_spectator(&mut self, _owner: &Node, address: String) -> PlayerHandle {
-        let remote_addr: std::net::SocketAddr = address.parse().unwrap();
-        self.add_player(PlayerType::Spectator(remote_addr))
+    pub fn clear_last_error(&mut self, _owner: &Node) {
+        self.last_error = None;
     }
 
     /// Starts the [P2PSession]
@@ -99,12 +456,43 @@ impl GodotGGRSP2PSession {
             Some(s) => match s.start_session() {
                 Ok(_) => godot_print!("Started GodotGGRS session"),
                 Err(e) => {
-                    godot_error!("{}", e);
+                    self.report(e.into());
                 }
             },
-            None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE)
-            }
+            None => self.report(GodotGGRSError::NoSession),
+        }
+    }
+
+    /// Closes the [P2PSession], which releases its UDP socket and clears its players, frame delays, events and settings, a running recording is stopped.
+    /// Emits [SIGNAL_SESSION_CLOSED], after which a new session can be created on the same port.
+    /// The callback node, rollback participants, input schema, network conditions and session metrics are kept.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    #[export]
+    pub fn close_session(&mut self, owner: &Node) {
+        if !self.close(owner) {
+            self.report(GodotGGRSError::NoSession);
+        }
+    }
+
+    /// Closes the [P2PSession] and creates it again with the same socket, players, frame delays and settings, so a rematch can be played against the same peers.
+    /// Players keep their handles, call [Self::start_session()] once the session is set up again.
+    /// # Notes
+    /// - A session that was created on port `0` is created again on the port the system picked, so peers can keep using the same address.
+    /// - Every peer has to reset its session as well, since the new session synchronizes from the first frame again.
+    /// - Emits [SIGNAL_SESSION_CLOSED] for the closed session, see [Self::close_session()].
+    /// - When recommended frame delays are applied, see [Self::set_adaptive_frame_delay()], local players get the recommended frame delay instead and [SIGNAL_FRAME_DELAY_CHANGED] is emitted for every player of which it changed.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print an error if the socket could not be bound again or a player could not be added again, the session is closed but keeps its players and settings so resetting can be retried
+    #[export]
+    pub fn reset_for_rematch(&mut self, owner: &Node) {
+        let mut reset = SessionReset {
+            session: self,
+            owner,
+        };
+        if let Err(RematchError::NoSession) = rematch::reset_for_rematch(&mut reset) {
+            self.report(GodotGGRSError::NoSession);
         }
     }
 
@@ -127,46 +515,149 @@ impl GodotGGRSP2PSession {
                 SessionState::Synchronizing => "Synchronizing".to_owned(),
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 "".to_owned()
             }
         }
     }
 
-    /// This function will advance the frame using the inputs given as a parameter (currently an int in Godot)
-    /// Before using this function you have to set the callback node and make sure it has the following callback functions implemented
+    /// This function will advance the frame using the inputs given as a parameter (an int in Godot)
+    /// This only works when the session was created with an input size of [DEFAULT_INPUT_SIZE], use [Self::advance_frame_bytes()] for any other input size.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
     /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
     /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
-    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// Returns the status of the frame as one of the constants `ADVANCE_ADVANCED`, `ADVANCE_SKIPPED` (see [Self::set_auto_frame_skip()]), `ADVANCE_NOT_RUNNING` or `ADVANCE_FAILED`.
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
-    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if a callback node has not been set
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if the input size of the session is not [DEFAULT_INPUT_SIZE]
     #[export]
-    pub fn advance_frame(&mut self, _owner: &Node, local_player_handle: usize, local_input: u32) {
+    pub fn advance_frame(
+        &mut self,
+        owner: &Node,
+        local_player_handle: usize,
+        local_input: u32,
+    ) -> i64 {
         //Convert local_input into a byte array
         let local_input_bytes = local_input.to_be_bytes();
-        let local_input_array_slice: &[u8] = &local_input_bytes[..];
+        self.advance_frame_with_bytes(owner, local_player_handle, &local_input_bytes[..])
+            .code()
+    }
 
-        match self.callback_node {
-            Some(callback_node) => match &mut self.sess {
-                Some(s) => match s.advance_frame(local_player_handle, local_input_array_slice) {
-                    Ok(requests) => {
-                        ggrs_request_handlers::handle_requests(&callback_node, requests);
-                    }
-                    Err(e) => {
-                        godot_error!("{}", e);
-                    }
-                },
-                None => {
-                    godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
-                }
-            },
+    /// This function will advance the frame using the inputs given as a parameter (a `PoolByteArray` in Godot)
+    /// The size of the `PoolByteArray` should match the input size the session was created with.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
+    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// Returns the status of the frame as one of the constants `ADVANCE_ADVANCED`, `ADVANCE_SKIPPED` (see [Self::set_auto_frame_skip()]), `ADVANCE_NOT_RUNNING` or `ADVANCE_FAILED`.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if the size of the input does not match the input size of the session
+    #[export]
+    pub fn advance_frame_bytes(
+        &mut self,
+        owner: &Node,
+        local_player_handle: usize,
+        local_input: ByteArray,
+    ) -> i64 {
+        let local_input_bytes = local_input.read();
+        self.advance_frame_with_bytes(owner, local_player_handle, &local_input_bytes[..])
+            .code()
+    }
+
+    /// This function will advance the frame using the inputs given as a parameter (a `Dictionary` in Godot)
+    /// The `Dictionary` is packed into the input buffer using the schema set with [Self::set_input_schema()], fields that are missing are packed as zero while unknown keys and values of the wrong type are errors.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
+    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// Returns the status of the frame as one of the constants `ADVANCE_ADVANCED`, `ADVANCE_SKIPPED` (see [Self::set_auto_frame_skip()]), `ADVANCE_NOT_RUNNING` or `ADVANCE_FAILED`.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_NO_INPUT_SCHEMA] error if no input schema has been set
+    /// - Will print an error if the `Dictionary` could not be packed using the input schema, for example because it contains a key that is not part of the schema or a float instead of an int
+    #[export]
+    pub fn advance_frame_dict(
+        &mut self,
+        owner: &Node,
+        local_player_handle: usize,
+        local_input: Dictionary,
+    ) -> i64 {
+        let local_input_bytes = match &self.input_schema {
+            Some(schema) => ggrs_request_handlers::dictionary_to_input(schema, &local_input),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_CALLBACK_NODE);
+                self.report(GodotGGRSError::NoInputSchema);
+                return AdvanceFrameStatus::Failed.code();
             }
+        };
+        let status = match local_input_bytes {
+            Ok(bytes) => self.advance_frame_with_bytes(owner, local_player_handle, &bytes),
+            Err(e) => {
+                self.report(e.into());
+                AdvanceFrameStatus::Failed
+            }
+        };
+        status.code()
+    }
+
+    /// Sets the input schema used to pack a `Dictionary` into the input buffer, see [Self::advance_frame_dict()].
+    /// Once a schema is set the inputs given to [CALLBACK_FUNC_ADVANCE_FRAME] will be a `Dictionary` as well.
+    /// The schema is a `Dictionary` mapping field names to field types, fields are packed in the order they are inserted.
+    /// Supported field types are `"bool"`, `"i8"`, `"u8"`, `"i16"`, `"u16"`, `"i32"`, `"u32"` and `"bits(n)"` for an unsigned integer of `n` bits.
+    /// # Example
+    /// ```gdscript
+    /// p2p.set_input_schema({"buttons": "bits(12)", "stick_x": "i8", "stick_y": "i8"})
+    /// p2p.create_new_session(7070, 2, 8)
+    /// ```
+    /// # Errors
+    /// - Will print an error if the schema could not be parsed
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if a session has been made with an input size that does not match the schema
+    #[export]
+    pub fn set_input_schema(&mut self, _owner: &Node, schema: Dictionary) {
+        match ggrs_request_handlers::dictionary_to_input_schema(&schema) {
+            Ok(schema) => match &self.sess {
+                Some(s) if s.input_size() != schema.input_size() => {
+                    self.report(GodotGGRSError::BadInputSize)
+                }
+                _ => self.input_schema = Some(schema),
+            },
+            Err(e) => self.report(e.into()),
+        }
+    }
+
+    /// Enables passing a second argument to [CALLBACK_FUNC_ADVANCE_FRAME], which is true when the frame is re-simulated after a rollback.
+    /// Disabled by default, so callbacks that only declare the inputs parameter keep working. Enable it once your callback declares the second parameter.
+    #[export]
+    pub fn set_resimulation_flag(&mut self, _owner: &Node, enabled: bool) {
+        self.resimulation_flag = enabled;
+    }
+
+    /// Enables or disables automatic frame skipping, which is disabled by default.
+    /// When enabled [GGRSEvent::WaitRecommendation] events are consumed by the session instead of being emitted, and the advance frame functions will skip the recommended amount of frames by themselves.
+    /// This turns the game loop into a single call, the returned status tells if the frame was advanced (`ADVANCE_ADVANCED`), skipped (`ADVANCE_SKIPPED`) or if the session is not running yet (`ADVANCE_NOT_RUNNING`). Errors like a missing session return `ADVANCE_FAILED`, so they do not look like a session that is still synchronizing.
+    /// # Example
+    /// ```gdscript
+    /// func _physics_process(_delta):
+    ///     p2p.advance_frame(local_handle, get_local_input())
+    /// ```
+    #[export]
+    pub fn set_auto_frame_skip(&mut self, _owner: &Node, enabled: bool) {
+        self.auto_frame_skip = enabled;
+        if !enabled {
+            self.frames_to_skip = 0;
         }
     }
 
+    /// Returns the amount of frames that will still be skipped when automatic frame skipping is enabled.
+    #[export]
+    pub fn get_frames_to_skip(&mut self, _owner: &Node) -> u32 {
+        self.frames_to_skip
+    }
+
     /// Sets [P2PSession::set_fps()]
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
@@ -174,10 +665,14 @@ impl GodotGGRSP2PSession {
     pub fn set_fps(&mut self, _owner: &Node, fps: u32) {
         match &mut self.sess {
             Some(s) => match s.set_fps(fps) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    if let Some(config) = &mut self.config {
+                        config.fps = Some(fps);
+                    }
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -187,15 +682,46 @@ impl GodotGGRSP2PSession {
         self.callback_node = Some(callback);
     }
 
+    /// Adds a rollback participant that will be called when using [Self::advance_frame()], this is an alternative to using a single callback node.
+    /// Every participant implements the callback functions itself and saves and loads its own slice of the game state.
+    /// The slices are concatenated into a single state buffer in the order the participants were added, so participants should not change while the session is running.
+    /// Once any participant has been added the callback node is no longer called.
+    /// # Example
+    /// ```gdscript
+    /// for node in get_tree().get_nodes_in_group("rollback"):
+    ///     p2p.add_rollback_participant(node)
+    /// ```
+    #[export]
+    pub fn add_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        if !self.rollback_participants.contains(&participant) {
+            self.rollback_participants.push(participant);
+        }
+    }
+
+    /// Removes a rollback participant that was added with [Self::add_rollback_participant()].
+    #[export]
+    pub fn remove_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        self.rollback_participants.retain(|p| *p != participant);
+    }
+
+    /// Removes all rollback participants, after which the callback node will be called again.
+    #[export]
+    pub fn clear_rollback_participants(&mut self, _owner: &Node) {
+        self.rollback_participants.clear();
+    }
+
     /// Calls [P2PSession::poll_remote_clients()]
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn poll_remote_clients(&mut self, _owner: &Node) {
+    pub fn poll_remote_clients(&mut self, owner: &Node) {
+        self.receive_from_transport();
         match &mut self.sess {
             Some(s) => s.poll_remote_clients(),
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
+        self.send_to_transport();
+        self.handle_events(owner);
     }
 
     /// Prints out network stats of specified handle
@@ -206,43 +732,90 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => match s.network_stats(handle) {
                 Ok(n) => godot_print!("send_queue_len: {0}; ping: {1}; kbps_sent: {2}; local_frames_behind: {3}; remote_frames_behind: {4};", n.send_queue_len, n.ping, n.kbps_sent, n.local_frames_behind, n.remote_frames_behind),
-                Err(e) => godot_error!("{}", e),
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
-    /// Will return network stats of specified handle as a `tuple`, which will be converted to an `Array` inside godot.
+    /// Will return network stats of specified handle as a `Dictionary` with the keys `send_queue_len`, `ping`, `kbps_sent`, `local_frames_behind` and `remote_frames_behind`.
+    /// Returns an empty `Dictionary` if the stats could not be retrieved.
+    /// # Example
+    /// ```gdscript
+    /// var stats = p2p.get_network_stats(remote_handle)
+    /// $Ping.text = "Ping: %d" % stats.get("ping", 0)
+    /// ```
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn get_network_stats(
-        &mut self,
-        _owner: &Node,
-        handle: PlayerHandle,
-    ) -> (usize, u64, usize, i32, i32) {
-        const DEFAULT_RESPONSE: (usize, u64, usize, i32, i32) = (0, 0, 0, 0, 0);
+    pub fn get_network_stats(&mut self, _owner: &Node, handle: PlayerHandle) -> Dictionary {
         match &mut self.sess {
             Some(s) => match s.network_stats(handle) {
-                Ok(n) => (
-                    n.send_queue_len,
-                    n.ping as u64,
-                    n.kbps_sent,
-                    n.local_frames_behind,
-                    n.remote_frames_behind,
-                ),
+                Ok(n) => ggrs_event_handlers::network_stats_to_dictionary(&n).into_shared(),
                 Err(e) => {
-                    godot_error!("{}", e);
-                    DEFAULT_RESPONSE
+                    self.report(e.into());
+                    Dictionary::new_shared()
                 }
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
-                DEFAULT_RESPONSE
+                self.report(GodotGGRSError::NoSession);
+                Dictionary::new_shared()
             }
         }
     }
 
+    /// Sets the interval in milliseconds at which [SIGNAL_NETWORK_STATS_UPDATED] is emitted for every remote player and spectator, `0` disables the signal.
+    /// The signal is disabled by default and is only emitted during [Self::poll_remote_clients()] and [Self::advance_frame()], so the interval is never shorter than the time between those calls.
+    #[export]
+    pub fn set_network_stats_interval(&mut self, _owner: &Node, interval_ms: u64) {
+        self.network_stats_interval = match interval_ms {
+            0 => None,
+            _ => Some(Duration::from_millis(interval_ms)),
+        };
+        self.last_network_stats_update = Instant::now();
+    }
+
+    /// Delays every message the session receives by the given amount of milliseconds, to reproduce a bad connection on one machine.
+    /// Only received messages are affected, so the conditions should be set on both ends to delay messages in both directions.
+    #[export]
+    pub fn set_simulated_latency(&mut self, _owner: &Node, ms: u64) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.latency = Duration::from_millis(ms)
+        });
+    }
+
+    /// Adds a random delay between `0` and the given amount of milliseconds to every message the session receives, on top of [Self::set_simulated_latency()].
+    #[export]
+    pub fn set_jitter(&mut self, _owner: &Node, ms: u64) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.jitter = Duration::from_millis(ms)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is dropped.
+    #[export]
+    pub fn set_packet_loss(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.packet_loss = network_conditioner::clamp_percent(percent)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is received twice.
+    #[export]
+    pub fn set_duplicate_rate(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.duplicate_rate = network_conditioner::clamp_percent(percent)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is held back until after the messages that follow it.
+    #[export]
+    pub fn set_reorder_rate(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.reorder_rate = network_conditioner::clamp_percent(percent)
+        });
+    }
+
     /// Sets [P2PSession::set_frame_delay()] of specified handle.
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
@@ -255,10 +828,60 @@ impl GodotGGRSP2PSession {
     ) {
         match &mut self.sess {
             Some(s) => match s.set_frame_delay(frame_delay, player_handle) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    self.frame_delays.insert(player_handle, frame_delay);
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
+        }
+    }
+
+    /// Enables or disables adaptive frame delay, which samples the ping to remote players and the depth of rollbacks while advancing frames.
+    /// The ping is sampled at the interval set with [Self::set_network_stats_interval()], or twice a second when no interval was set.
+    /// The frame delay recommended for the next round is returned by [Self::get_recommended_frame_delay()].
+    /// When `apply_on_rematch` is true, [Self::reset_for_rematch()] sets the recommended frame delay for all local players and emits [SIGNAL_FRAME_DELAY_CHANGED].
+    /// # Notes
+    /// - GGRS can not change the frame delay of a running session, so recommendations are only applied between rounds.
+    /// - Samples are discarded whenever a session is created, including the session created by [Self::reset_for_rematch()].
+    #[export]
+    pub fn set_adaptive_frame_delay(
+        &mut self,
+        _owner: &Node,
+        enabled: bool,
+        #[opt] apply_on_rematch: bool,
+    ) {
+        self.adaptive_frame_delay = enabled;
+        self.apply_recommended_frame_delay = apply_on_rematch;
+    }
+
+    /// Returns the frame delay that is recommended for the local player in the next round, based on the ping and rollbacks of the current round.
+    /// Returns the current frame delay of the player when adaptive frame delay is disabled or no ping has been measured yet.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print an `ERR_NOT_LOCAL_PLAYER` error if the handle does not belong to a local player
+    #[export]
+    pub fn get_recommended_frame_delay(
+        &mut self,
+        _owner: &Node,
+        player_handle: PlayerHandle,
+    ) -> u32 {
+        let fps = match &self.config {
+            Some(config) => config.fps.unwrap_or(frame_delay_tuning::DEFAULT_FPS),
+            None => {
+                self.report(GodotGGRSError::NoSession);
+                return 0;
+            }
+        };
+        if !self.local_handles.contains(&player_handle) {
+            self.report(GodotGGRSError::NotLocalPlayer(player_handle));
+            return 0;
+        }
+        let frame_delay = self.frame_delays.get(&player_handle).copied().unwrap_or(0);
+        if self.adaptive_frame_delay {
+            self.frame_delay_tuner.recommend(frame_delay, fps)
+        } else {
+            frame_delay
         }
     }
 
@@ -267,9 +890,13 @@ impl GodotGGRSP2PSession {
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
     pub fn set_disconnect_timeout(&mut self, _owner: &Node, secs: u64) {
-        match &mut self.sess {
-            Some(s) => s.set_disconnect_timeout(std::time::Duration::from_secs(secs)),
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+        let timeout = Duration::from_secs(secs);
+        match (&mut self.sess, &mut self.config) {
+            (Some(s), Some(config)) => {
+                s.set_disconnect_timeout(timeout);
+                config.disconnect_timeout = Some(timeout);
+            }
+            _ => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -278,9 +905,13 @@ impl GodotGGRSP2PSession {
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
     pub fn set_disconnect_notify_delay(&mut self, _owner: &Node, secs: u64) {
-        match &mut self.sess {
-            Some(s) => s.set_disconnect_notify_delay(std::time::Duration::from_secs(secs)),
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+        let delay = Duration::from_secs(secs);
+        match (&mut self.sess, &mut self.config) {
+            (Some(s), Some(config)) => {
+                s.set_disconnect_notify_delay(delay);
+                config.disconnect_notify_delay = Some(delay);
+            }
+            _ => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -291,10 +922,14 @@ impl GodotGGRSP2PSession {
     pub fn set_sparse_saving(&mut self, _owner: &Node, sparse_saving: bool) {
         match &mut self.sess {
             Some(s) => match s.set_sparse_saving(sparse_saving) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    if let Some(config) = &mut self.config {
+                        config.sparse_saving = Some(sparse_saving);
+                    }
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -306,14 +941,16 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => match s.disconnect_player(player_handle) {
                 Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
     /// Returns an `Array` of events which contain usefull information, while you don't have to implement everything, the one thing you should implement is the WaitRecommendation.
     /// For details regarding the events please take a loot at [GGRSEvent].
+    /// Every event is also emitted as a signal, connecting to the signals of this node is the recommended alternative to polling this function.
+    /// At most 100 events are kept between calls, older events are discarded.
     /// # Example
     /// ```gdscript
     /// var events = ggrs.get_events()
@@ -338,43 +975,15 @@ impl GodotGGRSP2PSession {
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn get_events(&mut self, _owner: &Node) -> Vec<(&str, Variant)> {
-        let mut result: Vec<(&str, Variant)> = Vec::new();
-        match &mut self.sess {
-            Some(s) => {
-                for event in s.events() {
-                    match event {
-                        GGRSEvent::WaitRecommendation { skip_frames } => {
-                            result.push(("WaitRecommendation", skip_frames.to_variant()))
-                        }
-                        GGRSEvent::NetworkInterrupted {
-                            player_handle,
-                            disconnect_timeout,
-                        } => result.push((
-                            "NetworkInterrupted",
-                            (player_handle, disconnect_timeout as u64).to_variant(),
-                        )),
-                        GGRSEvent::NetworkResumed { player_handle } => {
-                            result.push(("NetworkResumed", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Disconnected { player_handle } => {
-                            result.push(("Disconnected", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Synchronized { player_handle } => {
-                            result.push(("Synchronized", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Synchronizing {
-                            player_handle,
-                            total,
-                            count,
-                        } => result
-                            .push(("Synchronizing", (player_handle, total, count).to_variant())),
-                    }
-                }
-            }
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
-        };
-        return result;
+    pub fn get_events(&mut self, owner: &Node) -> Vec<(&str, Variant)> {
+        if self.sess.is_none() {
+            self.report(GodotGGRSError::NoSession);
+        }
+        self.handle_events(owner);
+        self.events
+            .drain(..)
+            .map(ggrs_event_handlers::event_to_tuple)
+            .collect()
     }
 
     /// Calls and returns [P2PSession::frames_ahead()].
@@ -386,7 +995,7 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => s.frames_ahead(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 0
             }
         }
@@ -401,7 +1010,7 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => s.max_prediction(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 return 0;
             }
         }
@@ -416,7 +1025,7 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => s.current_frame(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 return 0;
             }
         }
@@ -431,29 +1040,425 @@ impl GodotGGRSP2PSession {
         match &mut self.sess {
             Some(s) => s.confirmed_frame(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 return 0;
             }
         }
     }
 
+    /// Returns statistics about the requests handled by this session as a `Dictionary`, see [SessionMetrics].
+    /// Contains the keys `load_count`, `save_count`, `advance_count`, `resimulated_frames`, `max_rollback_depth`, `rollback_depths` (a `Dictionary` mapping a rollback depth to how often it happened)
+    /// and the average and peak time spent in each callback in microseconds, like `average_advance_usec` and `peak_load_usec`.
+    #[export]
+    pub fn get_session_metrics(&mut self, _owner: &Node) -> Dictionary {
+        ggrs_request_handlers::session_metrics_to_dictionary(&self.metrics).into_shared()
+    }
+
+    /// Resets the statistics returned by [Self::get_session_metrics()].
+    #[export]
+    pub fn reset_session_metrics(&mut self, _owner: &Node) {
+        self.metrics.reset();
+    }
+
+    /// Starts recording a replay of the session to the file at `path`, which can also be a Godot path like `"user://last_match.ggrsreplay"`.
+    /// The replay contains the settings of the session (players, input size, max prediction and frame delays) and the inputs of all players of every confirmed frame.
+    /// Frames are written and flushed as soon as they are confirmed, so the file stays usable when the game crashes. A recording that is already running is stopped first.
+    /// # Notes
+    /// - Start the recording after adding all players and setting their frame delays, but before advancing the first frame.
+    /// - Inputs of players that have disconnected are recorded as zeroes.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print an error if the file could not be created
+    #[export]
+    pub fn start_recording(&mut self, owner: &Node, path: String) {
+        let header = match &self.sess {
+            Some(s) => ReplayHeader {
+                num_players: s.num_players(),
+                input_size: s.input_size(),
+                max_prediction: s.max_prediction(),
+                players: self.replay_players(),
+            },
+            None => {
+                self.report(GodotGGRSError::NoSession);
+                return;
+            }
+        };
+        self.stop_recording(owner);
+        let path = ProjectSettings::godot_singleton()
+            .globalize_path(path)
+            .to_string();
+        match File::create(&path)
+            .and_then(|file| ReplayRecorder::new(BufWriter::new(file), &header))
+        {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => self.report(GodotGGRSError::ReplayFile(format!(
+                "Could not create replay file \"{}\": {}.",
+                path, e
+            ))),
+        }
+    }
+
+    /// Stops the recording that was started with [Self::start_recording()], inputs of frames that have not been confirmed yet are not written.
+    /// Does nothing when no recording is running.
+    /// # Errors
+    /// - Will print an error if the file could not be flushed
+    #[export]
+    pub fn stop_recording(&mut self, _owner: &Node) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish() {
+                self.report(GodotGGRSError::ReplayFile(format!(
+                    "Could not write to the replay file: {}.",
+                    e
+                )));
+            }
+        }
+    }
+
+    /// Returns true while a recording started with [Self::start_recording()] is running.
+    #[export]
+    pub fn is_recording(&mut self, _owner: &Node) -> bool {
+        self.recorder.is_some()
+    }
+
     //NON-EXPORTED FUNCTIONS
-    fn add_player(&mut self, player_type: PlayerType) -> PlayerHandle {
-        match &mut self.sess {
-            Some(s) => match s.add_player(player_type, self.next_handle) {
-                Ok(o) => {
-                    self.next_handle += 1;
-                    return o;
+    /// Opens the socket and creates the session, a session that is still open is closed first so its port can be bound again.
+    /// Returns false if the socket could not be opened.
+    fn open_session(
+        &mut self,
+        owner: &Node,
+        socket: SessionSocket,
+        num_players: u32,
+        max_pred: usize,
+        input_size: usize,
+    ) -> bool {
+        self.close(owner);
+        let opened = match socket.open() {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.report(e);
+                return false;
+            }
+        };
+        let input_size = match &self.input_schema {
+            Some(schema) if input_size == 0 => schema.input_size(),
+            _ if input_size == 0 => DEFAULT_INPUT_SIZE,
+            _ => input_size,
+        };
+        let (socket, checksum_channel) = WrapperSocket::new(opened.transport);
+        let socket = NetworkConditioner::new(socket, self.network_conditions.clone());
+        self.sess = Some(P2PSession::new_with_socket(
+            num_players,
+            input_size,
+            max_pred,
+            socket,
+        ));
+        self.checksum_channel = Some(checksum_channel);
+        self.frame_delay_tuner.reset();
+        self.transport = opened.godot_transport;
+        self.local_addr = opened.local_addr;
+        self.config = Some(SessionConfig {
+            socket: opened.socket,
+            num_players,
+            max_pred,
+            input_size,
+            players: Vec::new(),
+            fps: None,
+            disconnect_timeout: None,
+            disconnect_notify_delay: None,
+            sparse_saving: None,
+        });
+        true
+    }
+
+    /// Drops the session and everything that belongs to it, which releases the socket.
+    /// Returns false if no session was open, otherwise [SIGNAL_SESSION_CLOSED] is emitted.
+    fn close(&mut self, owner: &Node) -> bool {
+        if self.sess.is_none() {
+            return false;
+        }
+        self.stop_recording(owner);
+        //The checksum channel shares the transport with the socket of the session, so both have to be dropped
+        self.sess = None;
+        self.checksum_channel = None;
+        self.transport = None;
+        self.local_addr = None;
+        self.config = None;
+        self.next_handle = 0;
+        self.events.clear();
+        self.frames_to_skip = 0;
+        self.remote_handles.clear();
+        self.remote_players.clear();
+        self.local_handles.clear();
+        self.frame_delays.clear();
+        self.desync_detector = DesyncDetector::new();
+        ggrs_event_handlers::emit_session_closed_signal(owner);
+        true
+    }
+
+    /// Resolves the address of a peer, addresses of a transport node are used as they are.
+    fn peer_address(&self, address: &str) -> Result<String, GodotGGRSError> {
+        match self.transport {
+            Some(_) => Ok(address.to_owned()),
+            None => address::resolve_socket_addr(address, self.address_preference)
+                .map(transport::format_addr)
+                .map_err(GodotGGRSError::from),
+        }
+    }
+
+    fn receive_from_transport(&self) {
+        if let Some(transport) = &self.transport {
+            transport.receive();
+        }
+    }
+
+    fn send_to_transport(&self) {
+        if let Some(transport) = &self.transport {
+            transport.send();
+        }
+    }
+
+    fn handle_events(&mut self, owner: &Node) {
+        if let Some(s) = &mut self.sess {
+            for event in s.events() {
+                if let (true, GGRSEvent::WaitRecommendation { skip_frames }) =
+                    (self.auto_frame_skip, event)
+                {
+                    self.frames_to_skip += skip_frames;
+                    continue;
                 }
-                Err(e) => {
-                    godot_error!("{}", e);
-                    panic!()
+                ggrs_event_handlers::emit_event_signal(owner, event);
+                if self.events.len() >= ggrs_event_handlers::MAX_EVENT_QUEUE_SIZE {
+                    self.events.pop_front();
+                }
+                self.events.push_back(event);
+            }
+        }
+        self.emit_network_stats(owner);
+        self.detect_desyncs(owner);
+    }
+
+    fn detect_desyncs(&mut self, owner: &Node) {
+        let (incompatible_peers, reports) = match &self.checksum_channel {
+            Some(channel) => (channel.incompatible_peers(), channel.receive()),
+            None => return,
+        };
+        for addr in incompatible_peers {
+            self.report(GodotGGRSError::IncompatiblePeer(addr));
+        }
+        for (addr, report) in reports {
+            let handle = self.remote_players.iter().find(|(_, a)| *a == addr);
+            if let Some((handle, _)) = handle {
+                self.desync_detector.record_remote(*handle, report);
+            }
+        }
+        for desync in self.desync_detector.detect() {
+            ggrs_event_handlers::emit_desync_signal(owner, &desync);
+        }
+    }
+
+    /// Emits the network stats and samples the ping for adaptive frame delay, once every network stats interval.
+    /// Adaptive frame delay samples the ping every [frame_delay_tuning::PING_SAMPLE_INTERVAL] when no interval was set.
+    fn emit_network_stats(&mut self, owner: &Node) {
+        let interval = match (self.network_stats_interval, self.adaptive_frame_delay) {
+            (Some(interval), _) => interval,
+            (None, true) => frame_delay_tuning::PING_SAMPLE_INTERVAL,
+            (None, false) => return,
+        };
+        if self.last_network_stats_update.elapsed() < interval {
+            return;
+        }
+        if let Some(s) = &self.sess {
+            for handle in &self.remote_handles {
+                //Stats are unavailable until the remote client has synchronized, so errors are skipped
+                if let Ok(stats) = s.network_stats(*handle) {
+                    if self.network_stats_interval.is_some() {
+                        ggrs_event_handlers::emit_network_stats_signal(owner, *handle, &stats);
+                    }
+                    //Spectators do not send inputs, so their ping does not affect the frame delay
+                    if self.adaptive_frame_delay
+                        && self.remote_players.iter().any(|(h, _)| h == handle)
+                    {
+                        self.frame_delay_tuner.record_ping(stats.ping);
+                    }
+                }
+            }
+        }
+        self.last_network_stats_update = Instant::now();
+    }
+
+    fn advance_frame_with_bytes(
+        &mut self,
+        owner: &Node,
+        local_player_handle: usize,
+        local_input: &[u8],
+    ) -> AdvanceFrameStatus {
+        self.receive_from_transport();
+        let status = self.advance_session(owner, local_player_handle, local_input);
+        self.send_to_transport();
+        status
+    }
+
+    fn advance_session(
+        &mut self,
+        owner: &Node,
+        local_player_handle: usize,
+        local_input: &[u8],
+    ) -> AdvanceFrameStatus {
+        if self.auto_frame_skip {
+            self.handle_events(owner);
+            match &mut self.sess {
+                Some(s) if s.current_state() != SessionState::Running => {
+                    s.poll_remote_clients();
+                    self.handle_events(owner);
+                    return AdvanceFrameStatus::NotRunning;
+                }
+                Some(s) if self.frames_to_skip > 0 => {
+                    self.frames_to_skip -= 1;
+                    s.poll_remote_clients();
+                    self.handle_events(owner);
+                    return AdvanceFrameStatus::Skipped;
+                }
+                _ => (),
+            }
+        }
+
+        let status = match ggrs_request_handlers::CallbackNodes::new(
+            &self.callback_node,
+            &self.rollback_participants,
+        ) {
+            Some(callback_nodes) => match &mut self.sess {
+                Some(s) if s.input_size() != local_input.len() => {
+                    self.report(GodotGGRSError::BadInputSize);
+                    AdvanceFrameStatus::Failed
+                }
+                Some(s) => match s.advance_frame(local_player_handle, local_input) {
+                    Ok(requests) => {
+                        let confirmation = InputConfirmation {
+                            confirmed_frame: s.confirmed_frame(),
+                            local_handles: &self.local_handles,
+                        };
+                        let handled = rollback_callbacks::handle_requests(
+                            &mut GodotCallbacks::new(
+                                callback_nodes,
+                                self.input_schema.as_ref(),
+                                self.resimulation_flag,
+                            ),
+                            requests,
+                            confirmation,
+                            &mut self.metrics,
+                        );
+                        for (frame, checksum) in handled.saved_checksums {
+                            self.desync_detector.record_local(frame, checksum);
+                        }
+                        if self.adaptive_frame_delay {
+                            for depth in handled.rollback_depths {
+                                self.frame_delay_tuner.record_rollback(depth);
+                            }
+                        }
+                        //Frames are only confirmed right after advancing, since inputs received while polling have not been re-simulated yet
+                        let confirmed_frame = s.confirmed_frame();
+                        let reports = self.desync_detector.confirm(confirmed_frame);
+                        if let Some(channel) = &self.checksum_channel {
+                            for report in reports {
+                                for (_, addr) in &self.remote_players {
+                                    channel.send(report, addr);
+                                }
+                            }
+                        }
+                        self.record_inputs(handled.advanced_inputs, confirmed_frame);
+                        AdvanceFrameStatus::Advanced
+                    }
+                    Err(GGRSError::NotSynchronized) => {
+                        self.report(GGRSError::NotSynchronized.into());
+                        AdvanceFrameStatus::NotRunning
+                    }
+                    Err(e) => {
+                        self.report(e.into());
+                        AdvanceFrameStatus::Failed
+                    }
+                },
+                None => {
+                    self.report(GodotGGRSError::NoSession);
+                    AdvanceFrameStatus::Failed
                 }
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
-                panic!()
+                self.report(GodotGGRSError::NoCallbackNode);
+                AdvanceFrameStatus::Failed
             }
         };
+        self.handle_events(owner);
+        status
+    }
+
+    fn record_inputs(
+        &mut self,
+        advanced_inputs: Vec<(Frame, Vec<Vec<u8>>)>,
+        confirmed_frame: Frame,
+    ) {
+        if let Some(recorder) = &mut self.recorder {
+            for (frame, inputs) in advanced_inputs {
+                recorder.record(frame, &inputs);
+            }
+            if let Err(e) = recorder.write_confirmed(confirmed_frame) {
+                self.recorder = None;
+                self.report(GodotGGRSError::ReplayFile(format!(
+                    "Could not write to the replay file, the recording has been stopped: {}.",
+                    e
+                )));
+            }
+        }
+    }
+
+    fn replay_players(&self) -> Vec<ReplayPlayer> {
+        let local_players = self.local_handles.iter().map(|handle| (*handle, true));
+        let remote_players = self
+            .remote_players
+            .iter()
+            .map(|(handle, _)| (*handle, false));
+        let mut players = local_players
+            .chain(remote_players)
+            .map(|(handle, is_local)| ReplayPlayer {
+                handle,
+                is_local,
+                frame_delay: self.frame_delays.get(&handle).copied().unwrap_or(0),
+            })
+            .collect::<Vec<_>>();
+        players.sort_by_key(|player| player.handle);
+        players
+    }
+
+    fn add_player(&mut self, player_type: PlayerType<String>) -> i64 {
+        match &mut self.sess {
+            Some(s) => match s.add_player(player_type.clone(), self.next_handle) {
+                Ok(o) => {
+                    self.next_handle += 1;
+                    if let Some(config) = &mut self.config {
+                        config.players.push(player_type.clone());
+                    }
+                    match player_type {
+                        PlayerType::Local => self.local_handles.push(o),
+                        PlayerType::Remote(addr) => {
+                            self.remote_handles.push(o);
+                            self.remote_players.push((o, addr));
+                        }
+                        PlayerType::Spectator(_) => self.remote_handles.push(o),
+                    }
+                    o as i64
+                }
+                Err(e) => self.player_error(e.into()),
+            },
+            None => self.player_error(GodotGGRSError::NoSession),
+        }
+    }
+
+    fn player_error(&mut self, error: GodotGGRSError) -> i64 {
+        self.report(error);
+        INVALID_PLAYER_HANDLE
+    }
+
+    fn report(&mut self, error: GodotGGRSError) {
+        godot_error!("{}", error);
+        self.last_error = Some(error);
     }
 }
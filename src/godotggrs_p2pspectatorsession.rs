@@ -1,13 +1,111 @@
+use crate::address::{self, AddressPreference};
+use crate::error::{self, GodotGGRSError};
+use crate::ggrs_request_handlers::GodotCallbacks;
+use crate::godot_transport::{self, GodotTransport, SessionSocket};
+use crate::input_schema::InputSchema;
+use crate::network_conditioner::{self, NetworkConditioner, SharedNetworkConditions};
+use crate::rematch::{self, RematchError, RematchSession};
+use crate::rollback_callbacks::{self, InputConfirmation};
+use crate::session_metrics::SessionMetrics;
+use crate::transport;
+use crate::wrapper_socket::{ChecksumChannel, WrapperSocket};
 use crate::*;
 use ggrs::{GGRSEvent, P2PSpectatorSession, SessionState};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::option::*;
+use std::time::Duration;
 
 /// A Godot implementation of [`P2PSpectatorSession`]
+/// # Signals
+/// All [GGRSEvent]s are emitted as signals during [Self::poll_remote_clients()] and [Self::advance_frame()]:
+/// - [SIGNAL_WAIT_RECOMMENDATION]
+/// - [SIGNAL_NETWORK_INTERRUPTED]
+/// - [SIGNAL_NETWORK_RESUMED]
+/// - [SIGNAL_DISCONNECTED]
+/// - [SIGNAL_SYNCHRONIZING]
+/// - [SIGNAL_SYNCHRONIZED]
+///
+/// [SIGNAL_SESSION_CLOSED] is emitted when the session is closed through [Self::close_session()] or [Self::reset_for_rematch()], or replaced by a new session.
+/// # Compatibility
+/// Every message is sent with a header containing the [protocol version](crate::wrapper_socket::PROTOCOL_VERSION) of GodotGGRS, like the messages of the host.
+/// Peers and spectators running another version, or plain GGRS, can not connect to each other. Messages from such a peer are dropped and reported once as `ERR_INCOMPATIBLE_PEER`.
+/// # Errors
+/// Failing functions print an error and record an error code, which can be retrieved with [Self::get_last_error_code()].
+/// All codes are exposed as integer constants on the node, like `ERR_NO_SESSION` or `ERR_NOT_SYNCHRONIZED`.
 #[derive(NativeClass)]
 #[inherit(Node)]
+#[register_with(Self::register)]
 pub struct GodotGGRSP2PSpectatorSession {
-    sess: Option<P2PSpectatorSession>,
+    sess: Option<P2PSpectatorSession<String>>,
     callback_node: Option<Ref<Node>>,
+    rollback_participants: Vec<Ref<Node>>,
+    input_schema: Option<InputSchema>,
+    resimulation_flag: bool,
+    events: VecDeque<GGRSEvent>,
+    address_preference: AddressPreference,
+    last_error: Option<GodotGGRSError>,
+    metrics: SessionMetrics,
+    network_conditions: SharedNetworkConditions,
+    transport: Option<GodotTransport>,
+    local_addr: Option<SocketAddr>,
+    checksum_channel: Option<ChecksumChannel>,
+    config: Option<SessionConfig>,
+}
+
+/// Everything a session was set up with, so [GodotGGRSP2PSpectatorSession::reset_for_rematch()] can set it up again.
+#[derive(Clone)]
+struct SessionConfig {
+    socket: SessionSocket,
+    num_players: u32,
+    host_addr: String,
+    input_size: usize,
+    fps: Option<u32>,
+    catchup_speed: Option<u32>,
+    max_frames_behind: Option<u32>,
+}
+
+/// Resets a [GodotGGRSP2PSpectatorSession] through [rematch::reset_for_rematch()], the owner is needed to emit signals.
+struct SessionReset<'a> {
+    session: &'a mut GodotGGRSP2PSpectatorSession,
+    owner: &'a Node,
+}
+
+impl RematchSession for SessionReset<'_> {
+    type Config = SessionConfig;
+
+    fn config(&self) -> Option<SessionConfig> {
+        self.session.config.clone()
+    }
+
+    fn open(&mut self, config: &SessionConfig) -> bool {
+        self.session.open_session(
+            self.owner,
+            config.socket.clone(),
+            config.num_players,
+            config.host_addr.clone(),
+            config.input_size,
+        )
+    }
+
+    fn restore(&mut self, config: &SessionConfig) -> bool {
+        let (session, owner) = (&mut *self.session, self.owner);
+        if let Some(fps) = config.fps {
+            session.set_fps(owner, fps);
+        }
+        if let Some(catchup_speed) = config.catchup_speed {
+            session.set_catchup_speed(owner, catchup_speed);
+        }
+        if let Some(max_frames_behind) = config.max_frames_behind {
+            session.set_max_frames_behind(owner, max_frames_behind);
+        }
+        true
+    }
+
+    fn abort(&mut self, config: SessionConfig) {
+        self.session.close(self.owner);
+        self.session.config = Some(config);
+    }
 }
 
 impl GodotGGRSP2PSpectatorSession {
@@ -15,8 +113,26 @@ impl GodotGGRSP2PSpectatorSession {
         GodotGGRSP2PSpectatorSession {
             sess: None,
             callback_node: None,
+            rollback_participants: Vec::new(),
+            input_schema: None,
+            resimulation_flag: false,
+            events: VecDeque::new(),
+            address_preference: AddressPreference::default(),
+            last_error: None,
+            metrics: SessionMetrics::new(),
+            network_conditions: SharedNetworkConditions::default(),
+            transport: None,
+            local_addr: None,
+            checksum_channel: None,
+            config: None,
         }
     }
+
+    fn register(builder: &ClassBuilder<Self>) {
+        ggrs_event_handlers::register_event_signals(builder);
+        ggrs_event_handlers::register_session_closed_signal(builder);
+        error::register_error_codes(builder);
+    }
 }
 
 #[methods]
@@ -28,23 +144,69 @@ impl GodotGGRSP2PSpectatorSession {
     }
 
     /// Creates a [P2PSpectatorSession], call this when you want to start setting up a `P2PSpectatorSession`.
-    /// Takes the local port and total number of players and the host address as parameters.
+    /// Takes the local port, total number of players, the host address and optionally the input size in bytes as parameters.
+    /// # Notes
+    /// - When the input size is omitted or `0` it defaults to [DEFAULT_INPUT_SIZE], the input size should match the input size of the host session.
+    /// - When an input schema has been set through [Self::set_input_schema()] and the input size is omitted, the input size of the schema is used.
+    /// - The host address can be a hostname like `"myhost.lan:7070"`, see [Self::set_address_preference()].
+    /// - The local port can also be a bind address as a String, like `"[::]:7070"`, see [GodotGGRSP2PSession::create_new_session()].
+    /// # Errors
+    /// - Will print an error if the host address could not be resolved to an [std::net::SocketAddr]
+    /// - Will print an error if the local address is not a port or a socket address, or if the socket could not be bound to it
     #[export]
     pub fn create_new_session(
         &mut self,
-        _owner: &Node,
-        local_port: u16,
+        owner: &Node,
+        local_address: Variant,
         num_players: u32,
         host_addr: String,
+        #[opt] input_size: usize,
     ) {
-        let input_size: usize = std::mem::size_of::<u32>();
-        let host_addr_object: std::net::SocketAddr = host_addr.parse().unwrap();
-        match P2PSpectatorSession::new(num_players, input_size, local_port, host_addr_object) {
-            Ok(s) => self.sess = Some(s),
-            Err(e) => godot_error!("{}", e),
+        let host_addr_object =
+            match address::resolve_socket_addr(&host_addr, self.address_preference) {
+                Ok(addr) => addr,
+                Err(e) => {
+                    self.report(e.into());
+                    return;
+                }
+            };
+        match godot_transport::parse_local_address(&local_address) {
+            Ok(local_addr) => {
+                self.open_session(
+                    owner,
+                    SessionSocket::Udp(local_addr),
+                    num_players,
+                    transport::format_addr(host_addr_object),
+                    input_size,
+                );
+            }
+            Err(e) => self.report(e),
         }
     }
 
+    /// Creates a [P2PSpectatorSession] that receives its messages through a transport node instead of binding its own UDP socket, see [Self::create_new_session()].
+    /// Takes the transport node, total number of players, the address of the host as the transport node knows it and optionally the input size in bytes as parameters.
+    /// # Notes
+    /// - The node needs a [TRANSPORT_FUNC_SEND_TO] function taking the address of a peer and a `PoolByteArray`, and a [TRANSPORT_FUNC_POLL] function returning an `Array` of `[address, PoolByteArray]` pairs.
+    /// - The node is polled during [Self::poll_remote_clients()] and [Self::advance_frame()], datagrams are sent at the end of those calls.
+    #[export]
+    pub fn create_new_session_with_transport(
+        &mut self,
+        owner: &Node,
+        transport: Ref<Node>,
+        num_players: u32,
+        host_addr: String,
+        #[opt] input_size: usize,
+    ) {
+        self.open_session(
+            owner,
+            SessionSocket::Transport(transport),
+            num_players,
+            host_addr,
+            input_size,
+        );
+    }
+
     /// Deprecated method to create a [P2PSpectatorSession]. Use [Self::create_new_session()] instead.
     #[deprecated(since = "0.5.0", note = "please use `create_new_session()` instead")]
     #[export]
@@ -55,7 +217,84 @@ impl GodotGGRSP2PSpectatorSession {
         num_players: u32,
         host_addr: String,
     ) {
-        self.create_new_session(_owner, local_port, num_players, host_addr)
+        self.create_new_session(
+            _owner,
+            local_port.to_variant(),
+            num_players,
+            host_addr,
+            DEFAULT_INPUT_SIZE,
+        )
+    }
+
+    /// Sets which IP version is preferred when a hostname resolves to multiple addresses, the default is `"ipv4"`.
+    /// Accepts `"ipv4"`, `"ipv6"` or `"any"`, when no address of the preferred version is found the first resolved address is used.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_INVALID_ADDRESS_PREFERENCE] error if the preference is unknown
+    #[export]
+    pub fn set_address_preference(&mut self, _owner: &Node, preference: String) {
+        match AddressPreference::parse(&preference) {
+            Some(p) => self.address_preference = p,
+            None => self.report(GodotGGRSError::BadAddress(
+                ERR_MESSAGE_INVALID_ADDRESS_PREFERENCE.to_owned(),
+            )),
+        }
+    }
+
+    /// Returns the local port the UDP socket of the session is bound to, which is the port the system picked when the session was created with port `0`.
+    /// Returns `0` when the session receives its messages through a transport node.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    #[export]
+    pub fn get_local_port(&mut self, _owner: &Node) -> u16 {
+        match (&self.sess, self.local_addr) {
+            (None, _) => {
+                self.report(GodotGGRSError::NoSession);
+                0
+            }
+            (Some(_), Some(local_addr)) => local_addr.port(),
+            (Some(_), None) => 0,
+        }
+    }
+
+    /// Delays every message the session receives by the given amount of milliseconds, to reproduce a bad connection on one machine.
+    /// Only received messages are affected, so the conditions should be set on both ends to delay messages in both directions.
+    #[export]
+    pub fn set_simulated_latency(&mut self, _owner: &Node, ms: u64) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.latency = Duration::from_millis(ms)
+        });
+    }
+
+    /// Adds a random delay between `0` and the given amount of milliseconds to every message the session receives, on top of [Self::set_simulated_latency()].
+    #[export]
+    pub fn set_jitter(&mut self, _owner: &Node, ms: u64) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.jitter = Duration::from_millis(ms)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is dropped.
+    #[export]
+    pub fn set_packet_loss(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.packet_loss = network_conditioner::clamp_percent(percent)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is received twice.
+    #[export]
+    pub fn set_duplicate_rate(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.duplicate_rate = network_conditioner::clamp_percent(percent)
+        });
+    }
+
+    /// Sets the chance in percent, between `0` and `100`, that a message the session receives is held back until after the messages that follow it.
+    #[export]
+    pub fn set_reorder_rate(&mut self, _owner: &Node, percent: f32) {
+        network_conditioner::update_conditions(&self.network_conditions, |conditions| {
+            conditions.reorder_rate = network_conditioner::clamp_percent(percent)
+        });
     }
 
     /// Returns true if connection has been established with remote players and is ready to start advancing frames via [Self::advance_frame()]
@@ -77,7 +316,7 @@ impl GodotGGRSP2PSpectatorSession {
                 SessionState::Synchronizing => "Synchronizing".to_owned(),
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 "".to_owned()
             }
         }
@@ -91,14 +330,40 @@ impl GodotGGRSP2PSpectatorSession {
         match &mut self.sess {
             Some(s) => match s.start_session() {
                 Ok(_) => godot_print!("Started GodotGGRS session"),
-                Err(e) => {
-                    godot_error!("{}", e);
-                    panic!()
-                }
+                Err(e) => self.report(e.into()),
             },
-            None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE)
-            }
+            None => self.report(GodotGGRSError::NoSession),
+        }
+    }
+
+    /// Closes the [P2PSpectatorSession], which releases its UDP socket and clears its events and settings.
+    /// Emits [SIGNAL_SESSION_CLOSED], after which a new session can be created on the same port.
+    /// The callback node, rollback participants, input schema, network conditions and session metrics are kept.
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    #[export]
+    pub fn close_session(&mut self, owner: &Node) {
+        if !self.close(owner) {
+            self.report(GodotGGRSError::NoSession);
+        }
+    }
+
+    /// Closes the [P2PSpectatorSession] and creates it again with the same socket, host and settings, so the rematch of the host can be watched.
+    /// Call [Self::start_session()] once the host has reset its session as well.
+    /// # Notes
+    /// - A session that was created on port `0` is created again on the port the system picked, so the host can keep using the same address.
+    /// - Emits [SIGNAL_SESSION_CLOSED] for the closed session, see [Self::close_session()].
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print an error if the socket could not be bound again, the session keeps its host and settings so resetting can be retried
+    #[export]
+    pub fn reset_for_rematch(&mut self, owner: &Node) {
+        let mut reset = SessionReset {
+            session: self,
+            owner,
+        };
+        if let Err(RematchError::NoSession) = rematch::reset_for_rematch(&mut reset) {
+            self.report(GodotGGRSError::NoSession);
         }
     }
 
@@ -108,34 +373,104 @@ impl GodotGGRSP2PSpectatorSession {
         self.callback_node = Some(callback);
     }
 
+    /// Adds a rollback participant that will be called when using [Self::advance_frame()], this is an alternative to using a single callback node.
+    /// Every participant implements the callback functions itself and saves and loads its own slice of the game state.
+    /// The slices are concatenated into a single state buffer in the order the participants were added, so participants should not change while the session is running.
+    /// Once any participant has been added the callback node is no longer called.
+    /// # Example
+    /// ```gdscript
+    /// for node in get_tree().get_nodes_in_group("rollback"):
+    ///     spectator.add_rollback_participant(node)
+    /// ```
+    #[export]
+    pub fn add_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        if !self.rollback_participants.contains(&participant) {
+            self.rollback_participants.push(participant);
+        }
+    }
+
+    /// Removes a rollback participant that was added with [Self::add_rollback_participant()].
+    #[export]
+    pub fn remove_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        self.rollback_participants.retain(|p| *p != participant);
+    }
+
+    /// Removes all rollback participants, after which the callback node will be called again.
+    #[export]
+    pub fn clear_rollback_participants(&mut self, _owner: &Node) {
+        self.rollback_participants.clear();
+    }
+
+    /// Sets the input schema used to unpack the input buffer into a `Dictionary`, this should be the same schema the host session uses.
+    /// Once a schema is set the inputs given to [CALLBACK_FUNC_ADVANCE_FRAME] will be a `Dictionary`.
+    /// The schema is a `Dictionary` mapping field names to field types, fields are packed in the order they are inserted.
+    /// Supported field types are `"bool"`, `"i8"`, `"u8"`, `"i16"`, `"u16"`, `"i32"`, `"u32"` and `"bits(n)"` for an unsigned integer of `n` bits.
+    /// # Errors
+    /// - Will print an error if the schema could not be parsed
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if a session has been made with an input size that does not match the schema
+    #[export]
+    pub fn set_input_schema(&mut self, _owner: &Node, schema: Dictionary) {
+        match ggrs_request_handlers::dictionary_to_input_schema(&schema) {
+            Ok(schema) => match &self.sess {
+                Some(s) if s.input_size() != schema.input_size() => {
+                    self.report(GodotGGRSError::BadInputSize)
+                }
+                _ => self.input_schema = Some(schema),
+            },
+            Err(e) => self.report(e.into()),
+        }
+    }
+
+    /// Enables passing a second argument to [CALLBACK_FUNC_ADVANCE_FRAME], which is true when the frame is re-simulated after a rollback.
+    /// See [GodotGGRSP2PSession::set_resimulation_flag()], this should be enabled whenever the callback declares the second parameter.
+    #[export]
+    pub fn set_resimulation_flag(&mut self, _owner: &Node, enabled: bool) {
+        self.resimulation_flag = enabled;
+    }
+
     /// This function will advance the frame using the inputs received from the host_session.
-    /// Before using this function you have to set the callback node and make sure it has the following callback functions implemented
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
     /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
     /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
-    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
-    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if a callback node has not been set
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
     #[export]
-    pub fn advance_frame(&mut self, _owner: &Node) {
-        match self.callback_node {
-            Some(callback_node) => match &mut self.sess {
+    pub fn advance_frame(&mut self, owner: &Node) {
+        self.receive_from_transport();
+        match ggrs_request_handlers::CallbackNodes::new(
+            &self.callback_node,
+            &self.rollback_participants,
+        ) {
+            Some(callback_nodes) => match &mut self.sess {
                 Some(s) => match s.advance_frame() {
                     Ok(requests) => {
-                        ggrs_request_handlers::handle_requests(&callback_node, requests);
+                        rollback_callbacks::handle_requests(
+                            &mut GodotCallbacks::new(
+                                callback_nodes,
+                                self.input_schema.as_ref(),
+                                self.resimulation_flag,
+                            ),
+                            requests,
+                            InputConfirmation::ALL_CONFIRMED,
+                            &mut self.metrics,
+                        );
                     }
                     Err(e) => {
-                        godot_error!("{}", e);
+                        self.report(e.into());
                     }
                 },
                 None => {
-                    godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                    self.report(GodotGGRSError::NoSession);
                 }
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_CALLBACK_NODE);
+                self.report(GodotGGRSError::NoCallbackNode);
             }
         }
+        self.send_to_transport();
+        self.handle_events(owner);
     }
 
     /// Returns [P2PSpectatorSession::frames_behind_host()]
@@ -146,7 +481,7 @@ impl GodotGGRSP2PSpectatorSession {
         match &mut self.sess {
             Some(s) => return s.frames_behind_host(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 return 0;
             }
         }
@@ -159,10 +494,14 @@ impl GodotGGRSP2PSpectatorSession {
     pub fn set_catchup_speed(&mut self, _owner: &Node, desired_catchup_speed: u32) {
         match &mut self.sess {
             Some(s) => match s.set_catchup_speed(desired_catchup_speed) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    if let Some(config) = &mut self.config {
+                        config.catchup_speed = Some(desired_catchup_speed);
+                    }
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -173,10 +512,14 @@ impl GodotGGRSP2PSpectatorSession {
     pub fn set_max_frames_behind(&mut self, _owner: &Node, desired_value: u32) {
         match &mut self.sess {
             Some(s) => match s.set_max_frames_behind(desired_value) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    if let Some(config) = &mut self.config {
+                        config.max_frames_behind = Some(desired_value);
+                    }
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -184,11 +527,14 @@ impl GodotGGRSP2PSpectatorSession {
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn poll_remote_clients(&mut self, _owner: &Node) {
+    pub fn poll_remote_clients(&mut self, owner: &Node) {
+        self.receive_from_transport();
         match &mut self.sess {
             Some(s) => s.poll_remote_clients(),
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
+        self.send_to_transport();
+        self.handle_events(owner);
     }
 
     /// Sets [P2PSpectatorSession::set_fps()]
@@ -198,10 +544,14 @@ impl GodotGGRSP2PSpectatorSession {
     pub fn set_fps(&mut self, _owner: &Node, fps: u32) {
         match &mut self.sess {
             Some(s) => match s.set_fps(fps) {
-                Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Ok(_) => {
+                    if let Some(config) = &mut self.config {
+                        config.fps = Some(fps);
+                    }
+                }
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
@@ -213,41 +563,37 @@ impl GodotGGRSP2PSpectatorSession {
         match &mut self.sess {
             Some(s) => match s.network_stats() {
                 Ok(n) => godot_print!("send_queue_len: {0}; ping: {1}; kbps_sent: {2}; local_frames_behind: {3}; remote_frames_behind: {4};", n.send_queue_len, n.ping, n.kbps_sent, n.local_frames_behind, n.remote_frames_behind),
-                Err(e) => godot_error!("{}", e),
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
-    /// Will return network stats of host address as a `tuple`, which will be converted to an `Array` inside godot.
+    /// Will return network stats of the host as a `Dictionary` with the keys `send_queue_len`, `ping`, `kbps_sent`, `local_frames_behind` and `remote_frames_behind`.
+    /// Returns an empty `Dictionary` if the stats could not be retrieved.
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn get_network_stats(&mut self, _owner: &Node) -> (usize, u64, usize, i32, i32) {
-        const DEFAULT_RESPONSE: (usize, u64, usize, i32, i32) = (0, 0, 0, 0, 0);
+    pub fn get_network_stats(&mut self, _owner: &Node) -> Dictionary {
         match &mut self.sess {
             Some(s) => match s.network_stats() {
-                Ok(n) => (
-                    n.send_queue_len,
-                    n.ping as u64,
-                    n.kbps_sent,
-                    n.local_frames_behind,
-                    n.remote_frames_behind,
-                ),
+                Ok(n) => ggrs_event_handlers::network_stats_to_dictionary(&n).into_shared(),
                 Err(e) => {
-                    godot_error!("{}", e);
-                    DEFAULT_RESPONSE
+                    self.report(e.into());
+                    Dictionary::new_shared()
                 }
             },
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
-                DEFAULT_RESPONSE
+                self.report(GodotGGRSError::NoSession);
+                Dictionary::new_shared()
             }
         }
     }
 
     /// Returns an `Array` of events which contain usefull information, while you don't have to implement everything, the one thing you should implement is the WaitRecommendation.
     /// For details regarding the events please take a loot at [GGRSEvent].
+    /// Every event is also emitted as a signal, connecting to the signals of this node is the recommended alternative to polling this function.
+    /// At most 100 events are kept between calls, older events are discarded.
     /// # Example
     /// ```gdscript
     /// var events = ggrs.get_events()
@@ -272,42 +618,150 @@ impl GodotGGRSP2PSpectatorSession {
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
     #[export]
-    pub fn get_events(&mut self, _owner: &Node) -> Vec<(&str, Variant)> {
-        let mut result: Vec<(&str, Variant)> = Vec::new();
-        match &mut self.sess {
-            Some(s) => {
-                for event in s.events() {
-                    match event {
-                        GGRSEvent::WaitRecommendation { skip_frames } => {
-                            result.push(("WaitRecommendation", skip_frames.to_variant()))
-                        }
-                        GGRSEvent::NetworkInterrupted {
-                            player_handle,
-                            disconnect_timeout,
-                        } => result.push((
-                            "NetworkInterrupted",
-                            (player_handle, disconnect_timeout as u64).to_variant(),
-                        )),
-                        GGRSEvent::NetworkResumed { player_handle } => {
-                            result.push(("NetworkResumed", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Disconnected { player_handle } => {
-                            result.push(("Disconnected", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Synchronized { player_handle } => {
-                            result.push(("Synchronized", player_handle.to_variant()))
-                        }
-                        GGRSEvent::Synchronizing {
-                            player_handle,
-                            total,
-                            count,
-                        } => result
-                            .push(("Synchronizing", (player_handle, total, count).to_variant())),
-                    }
+    pub fn get_events(&mut self, owner: &Node) -> Vec<(&str, Variant)> {
+        if self.sess.is_none() {
+            self.report(GodotGGRSError::NoSession);
+        }
+        self.handle_events(owner);
+        self.events
+            .drain(..)
+            .map(ggrs_event_handlers::event_to_tuple)
+            .collect()
+    }
+
+    /// Returns the code of the last error that occurred, or `ERR_OK` if no error occurred since [Self::clear_last_error()].
+    /// The codes are available as constants on the session, like `ERR_NO_SESSION` or `ERR_INVALID_HANDLE`.
+    #[export]
+    pub fn get_last_error_code(&mut self, _owner: &Node) -> i64 {
+        error::last_error_code(&self.last_error)
+    }
+
+    /// Returns the message of the last error that occurred, or an empty String if no error occurred since [Self::clear_last_error()].
+    #[export]
+    pub fn get_last_error(&mut self, _owner: &Node) -> String {
+        self.last_error
+            .as_ref()
+            .map_or_else(String::new, |e| e.to_string())
+    }
+
+    /// Clears the last error, after which [Self::get_last_error_code()] returns `ERR_OK` until another error occurs.
+    #[export]
+    pub fn clear_last_error(&mut self, _owner: &Node) {
+        self.last_error = None;
+    }
+
+    /// Returns statistics about the requests handled by this session as a `Dictionary`, see [SessionMetrics].
+    /// Contains the keys `load_count`, `save_count`, `advance_count`, `resimulated_frames`, `max_rollback_depth`, `rollback_depths` (a `Dictionary` mapping a rollback depth to how often it happened)
+    /// and the average and peak time spent in each callback in microseconds, like `average_advance_usec` and `peak_load_usec`.
+    #[export]
+    pub fn get_session_metrics(&mut self, _owner: &Node) -> Dictionary {
+        ggrs_request_handlers::session_metrics_to_dictionary(&self.metrics).into_shared()
+    }
+
+    /// Resets the statistics returned by [Self::get_session_metrics()].
+    #[export]
+    pub fn reset_session_metrics(&mut self, _owner: &Node) {
+        self.metrics.reset();
+    }
+
+    //NON-EXPORTED FUNCTIONS
+    /// Opens the socket and creates the session, a session that is still open is closed first so its port can be bound again.
+    /// Returns false if the socket could not be opened.
+    fn open_session(
+        &mut self,
+        owner: &Node,
+        socket: SessionSocket,
+        num_players: u32,
+        host_addr: String,
+        input_size: usize,
+    ) -> bool {
+        self.close(owner);
+        let opened = match socket.open() {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.report(e);
+                return false;
+            }
+        };
+        let input_size = match &self.input_schema {
+            Some(schema) if input_size == 0 => schema.input_size(),
+            _ if input_size == 0 => DEFAULT_INPUT_SIZE,
+            _ => input_size,
+        };
+        //The host sends its messages through a WrapperSocket, which the spectator needs to understand
+        let (socket, checksum_channel) = WrapperSocket::new(opened.transport);
+        let socket = NetworkConditioner::new(socket, self.network_conditions.clone());
+        self.sess = Some(P2PSpectatorSession::new_with_socket(
+            num_players,
+            input_size,
+            socket,
+            host_addr.clone(),
+        ));
+        self.transport = opened.godot_transport;
+        self.local_addr = opened.local_addr;
+        self.checksum_channel = Some(checksum_channel);
+        self.config = Some(SessionConfig {
+            socket: opened.socket,
+            num_players,
+            host_addr,
+            input_size,
+            fps: None,
+            catchup_speed: None,
+            max_frames_behind: None,
+        });
+        true
+    }
+
+    /// Drops the session, which releases the socket.
+    /// Returns false if no session was open, otherwise [SIGNAL_SESSION_CLOSED] is emitted.
+    fn close(&mut self, owner: &Node) -> bool {
+        if self.sess.is_none() {
+            return false;
+        }
+        self.sess = None;
+        self.transport = None;
+        self.local_addr = None;
+        self.checksum_channel = None;
+        self.config = None;
+        self.events.clear();
+        ggrs_event_handlers::emit_session_closed_signal(owner);
+        true
+    }
+
+    fn receive_from_transport(&self) {
+        if let Some(transport) = &self.transport {
+            transport.receive();
+        }
+    }
+
+    fn send_to_transport(&self) {
+        if let Some(transport) = &self.transport {
+            transport.send();
+        }
+    }
+
+    fn handle_events(&mut self, owner: &Node) {
+        if let Some(s) = &mut self.sess {
+            for event in s.events() {
+                ggrs_event_handlers::emit_event_signal(owner, event);
+                if self.events.len() >= ggrs_event_handlers::MAX_EVENT_QUEUE_SIZE {
+                    self.events.pop_front();
                 }
+                self.events.push_back(event);
             }
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+        }
+        //Spectators do not detect desyncs, the channel only tells about peers of other versions
+        let incompatible_peers = match &self.checksum_channel {
+            Some(channel) => channel.incompatible_peers(),
+            None => Vec::new(),
         };
-        return result;
+        for addr in incompatible_peers {
+            self.report(GodotGGRSError::IncompatiblePeer(addr));
+        }
+    }
+
+    fn report(&mut self, error: GodotGGRSError) {
+        godot_error!("{}", error);
+        self.last_error = Some(error);
     }
 }
@@ -0,0 +1,407 @@
+use crate::error::{self, GodotGGRSError};
+use crate::ggrs_request_handlers::GodotCallbacks;
+use crate::input_schema::InputSchema;
+use crate::replay::{Replay, ReplayPlayback};
+use crate::rollback_callbacks::{self, InputConfirmation};
+use crate::session_metrics::SessionMetrics;
+use crate::*;
+use gdnative::api::ProjectSettings;
+use ggrs::{Frame, GGRSRequest};
+use std::fs::File;
+use std::io::BufReader;
+
+/// The amount of frames between the states that are saved to seek through a replay, unless changed with [GodotGGRSReplaySession::set_snapshot_interval()].
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 60;
+
+/// Plays back a replay recorded with [GodotGGRSP2PSession::start_recording()].
+/// The recorded inputs are passed to the same callback functions as the other sessions, so game code does not need to know it is watching a replay.
+/// # Errors
+/// Failing functions print an error and record an error code, which can be retrieved with [Self::get_last_error_code()].
+/// All codes are exposed as integer constants on the node, like `ERR_REPLAY_FILE` or `ERR_NO_CALLBACK_NODE`.
+#[derive(NativeClass)]
+#[inherit(Node)]
+#[register_with(Self::register)]
+pub struct GodotGGRSReplaySession {
+    playback: Option<ReplayPlayback>,
+    callback_node: Option<Ref<Node>>,
+    rollback_participants: Vec<Ref<Node>>,
+    input_schema: Option<InputSchema>,
+    resimulation_flag: bool,
+    playing: bool,
+    speed: f64,
+    pending_frames: f64,
+    snapshot_interval: usize,
+    last_error: Option<GodotGGRSError>,
+    metrics: SessionMetrics,
+}
+
+/// The status that is returned when advancing the playback, its discriminant is the code returned to Godot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlaybackStatus {
+    /// At least one frame has been advanced.
+    Advanced = 0,
+    /// No frame has been advanced, because the playback speed is below `1.0`.
+    Skipped = 1,
+    /// The playback is paused.
+    Paused = 2,
+    /// Every recorded frame has been advanced.
+    Finished = 3,
+    /// The frame could not be advanced because of an error.
+    Failed = 4,
+}
+
+/// The names and codes of all [PlaybackStatus]es, these are registered as constants on [GodotGGRSReplaySession].
+const PLAYBACK_STATUS_CODES: &[(&str, i64)] = &[
+    ("PLAYBACK_ADVANCED", PlaybackStatus::Advanced as i64),
+    ("PLAYBACK_SKIPPED", PlaybackStatus::Skipped as i64),
+    ("PLAYBACK_PAUSED", PlaybackStatus::Paused as i64),
+    ("PLAYBACK_FINISHED", PlaybackStatus::Finished as i64),
+    ("PLAYBACK_FAILED", PlaybackStatus::Failed as i64),
+];
+
+impl PlaybackStatus {
+    fn code(self) -> i64 {
+        self as i64
+    }
+}
+
+impl GodotGGRSReplaySession {
+    fn new(_owner: &Node) -> Self {
+        GodotGGRSReplaySession {
+            playback: None,
+            callback_node: None,
+            rollback_participants: Vec::new(),
+            input_schema: None,
+            resimulation_flag: false,
+            playing: false,
+            speed: 1.0,
+            pending_frames: 0.0,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            last_error: None,
+            metrics: SessionMetrics::new(),
+        }
+    }
+
+    fn register(builder: &ClassBuilder<Self>) {
+        error::register_error_codes(builder);
+        error::register_constants(builder, PLAYBACK_STATUS_CODES);
+    }
+}
+
+#[methods]
+impl GodotGGRSReplaySession {
+    //EXPORTED FUNCTIONS
+    #[export]
+    fn _ready(&self, _owner: &Node) {
+        godot_print!("GodotGGRSReplaySession _ready() called.");
+    }
+
+    /// Loads the replay file at `path`, which can also be a Godot path like `"user://last_match.ggrsreplay"`.
+    /// The playback starts paused at the first recorded frame, the game should be in the same state as when the recorded match started.
+    /// # Errors
+    /// - Will print an error if the file could not be read or is not a supported replay file
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if an input schema has been set that does not match the input size of the replay
+    #[export]
+    pub fn load_replay(&mut self, _owner: &Node, path: String) {
+        let path = ProjectSettings::godot_singleton()
+            .globalize_path(path)
+            .to_string();
+        let replay = File::open(&path)
+            .map_err(|e| GodotGGRSError::ReplayFile(format!("Could not open \"{}\": {}.", path, e)))
+            .and_then(|file| {
+                Replay::read_from(&mut BufReader::new(file)).map_err(GodotGGRSError::from)
+            });
+        match replay {
+            Ok(replay) => match &self.input_schema {
+                Some(schema) if schema.input_size() != replay.header.input_size => {
+                    self.report(GodotGGRSError::BadInputSize)
+                }
+                _ => {
+                    self.playback = Some(ReplayPlayback::new(replay, self.snapshot_interval));
+                    self.playing = false;
+                    self.pending_frames = 0.0;
+                }
+            },
+            Err(e) => self.report(e),
+        }
+    }
+
+    /// Returns the settings of the loaded replay as a `Dictionary` with the keys `num_players`, `input_size`, `max_prediction`, `frame_count`
+    /// and `players`, an `Array` containing a `Dictionary` with the keys `handle`, `is_local` and `frame_delay` for every recorded player.
+    /// Returns an empty `Dictionary` if no replay was loaded.
+    #[export]
+    pub fn get_replay_info(&mut self, _owner: &Node) -> Dictionary {
+        let replay = match &self.playback {
+            Some(playback) => playback.replay(),
+            None => return Dictionary::new_shared(),
+        };
+        let players = VariantArray::new();
+        for player in &replay.header.players {
+            let info = Dictionary::new();
+            info.insert("handle", player.handle);
+            info.insert("is_local", player.is_local);
+            info.insert("frame_delay", player.frame_delay);
+            players.push(info);
+        }
+        let result = Dictionary::new();
+        result.insert("num_players", replay.header.num_players);
+        result.insert("input_size", replay.header.input_size);
+        result.insert("max_prediction", replay.header.max_prediction);
+        result.insert("frame_count", replay.frames.len());
+        result.insert("players", players);
+        result.into_shared()
+    }
+
+    /// Resumes the playback, after which [Self::advance_frame()] advances frames again.
+    #[export]
+    pub fn play(&mut self, _owner: &Node) {
+        self.playing = true;
+    }
+
+    /// Pauses the playback, [Self::step()] can still be used to advance a single frame.
+    #[export]
+    pub fn pause(&mut self, _owner: &Node) {
+        self.playing = false;
+        self.pending_frames = 0.0;
+    }
+
+    /// Returns true if the playback is not paused.
+    #[export]
+    pub fn is_playing(&mut self, _owner: &Node) -> bool {
+        self.playing
+    }
+
+    /// Sets the amount of frames that [Self::advance_frame()] advances per call, the default is `1.0`.
+    /// Fractions are carried over to the next call, so a speed of `0.5` advances a frame every other call and `2.0` advances two frames per call.
+    #[export]
+    pub fn set_speed(&mut self, _owner: &Node, speed: f64) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Returns the speed set with [Self::set_speed()].
+    #[export]
+    pub fn get_speed(&mut self, _owner: &Node) -> f64 {
+        self.speed
+    }
+
+    /// Sets the amount of frames between the states that are saved to seek through the replay, the default is `60`.
+    /// A smaller interval makes seeking backwards faster at the cost of memory. Only applies to replays that are loaded afterwards.
+    #[export]
+    pub fn set_snapshot_interval(&mut self, _owner: &Node, frames: usize) {
+        self.snapshot_interval = frames.max(1);
+    }
+
+    /// Call this every frame to play back the replay, advances frames according to the speed set with [Self::set_speed()].
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
+    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// Returns the status of the playback as one of the constants `PLAYBACK_ADVANCED`, `PLAYBACK_SKIPPED`, `PLAYBACK_PAUSED`, `PLAYBACK_FINISHED` or `PLAYBACK_FAILED`.
+    /// The playback is paused once it has finished.
+    /// # Errors
+    /// - Will print an error if no replay was loaded
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    #[export]
+    pub fn advance_frame(&mut self, owner: &Node) -> i64 {
+        if !self.playing {
+            return PlaybackStatus::Paused.code();
+        }
+        self.pending_frames += self.speed;
+        let frames = self.pending_frames.floor();
+        self.pending_frames -= frames;
+        let mut status = PlaybackStatus::Skipped;
+        for _ in 0..frames as u64 {
+            status = self.step_playback();
+            if status != PlaybackStatus::Advanced {
+                break;
+            }
+        }
+        if status == PlaybackStatus::Finished {
+            self.pause(owner);
+        }
+        status.code()
+    }
+
+    /// Advances a single frame, also when the playback is paused.
+    /// Returns the status of the playback as one of the constants `PLAYBACK_ADVANCED`, `PLAYBACK_FINISHED` or `PLAYBACK_FAILED`.
+    /// # Errors
+    /// - Will print an error if no replay was loaded
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    #[export]
+    pub fn step(&mut self, _owner: &Node) -> i64 {
+        self.step_playback().code()
+    }
+
+    /// Moves the playback to the given frame, so that it is the frame that will be advanced next.
+    /// Seeking backwards loads the nearest saved state before the frame, after which the frames in between are advanced within this call.
+    /// Like after a rollback, the frames advanced after the loaded state are re-simulated, except for the last one. These are passed to [CALLBACK_FUNC_ADVANCE_FRAME_RESIM] when implemented, or flagged when [Self::set_resimulation_flag()] is enabled.
+    /// # Errors
+    /// - Will print an error if no replay was loaded
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    #[export]
+    pub fn seek(&mut self, _owner: &Node, frame: Frame) {
+        if !self.can_play() {
+            return;
+        }
+        if let Some(playback) = &mut self.playback {
+            let requests = playback.seek(frame);
+            self.handle_requests(requests);
+        }
+    }
+
+    /// Returns the frame that will be advanced next.
+    /// Will return a 0 if no replay was loaded.
+    #[export]
+    pub fn get_current_frame(&mut self, _owner: &Node) -> Frame {
+        self.playback
+            .as_ref()
+            .map_or(0, ReplayPlayback::current_frame)
+    }
+
+    /// Returns true when every recorded frame has been advanced, or when no replay was loaded.
+    #[export]
+    pub fn is_finished(&mut self, _owner: &Node) -> bool {
+        self.playback
+            .as_ref()
+            .map_or(true, ReplayPlayback::is_finished)
+    }
+
+    /// Sets the input schema used to unpack inputs into a `Dictionary`, see [GodotGGRSP2PSession::set_input_schema()].
+    /// The schema should be the same as the schema of the recorded session.
+    /// # Errors
+    /// - Will print an error if the schema could not be parsed
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if a replay has been loaded with an input size that does not match the schema
+    #[export]
+    pub fn set_input_schema(&mut self, _owner: &Node, schema: Dictionary) {
+        match ggrs_request_handlers::dictionary_to_input_schema(&schema) {
+            Ok(schema) => match &self.playback {
+                Some(p) if p.replay().header.input_size != schema.input_size() => {
+                    self.report(GodotGGRSError::BadInputSize)
+                }
+                _ => self.input_schema = Some(schema),
+            },
+            Err(e) => self.report(e.into()),
+        }
+    }
+
+    /// Enables passing a second argument to [CALLBACK_FUNC_ADVANCE_FRAME], see [GodotGGRSP2PSession::set_resimulation_flag()].
+    /// The flag is only true for the frames that are fast-forwarded after seeking backwards, see [Self::seek()]. Enable it when the callback declares the second parameter.
+    #[export]
+    pub fn set_resimulation_flag(&mut self, _owner: &Node, enabled: bool) {
+        self.resimulation_flag = enabled;
+    }
+
+    /// Sets the callback node that will be called when using [Self::advance_frame()]
+    #[export]
+    pub fn set_callback_node(&mut self, _owner: &Node, callback: Ref<Node>) {
+        self.callback_node = Some(callback);
+    }
+
+    /// Adds a rollback participant that will be called when using [Self::advance_frame()], this is an alternative to using a single callback node.
+    /// Every participant implements the callback functions itself and saves and loads its own slice of the game state.
+    /// The slices are concatenated into a single state buffer in the order the participants were added, so participants should not change while the replay is playing.
+    /// Once any participant has been added the callback node is no longer called.
+    #[export]
+    pub fn add_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        if !self.rollback_participants.contains(&participant) {
+            self.rollback_participants.push(participant);
+        }
+    }
+
+    /// Removes a rollback participant that was added with [Self::add_rollback_participant()].
+    #[export]
+    pub fn remove_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        self.rollback_participants.retain(|p| *p != participant);
+    }
+
+    /// Removes all rollback participants, after which the callback node will be called again.
+    #[export]
+    pub fn clear_rollback_participants(&mut self, _owner: &Node) {
+        self.rollback_participants.clear();
+    }
+
+    /// Returns the code of the last error that occurred, or `ERR_OK` if no error occurred since [Self::clear_last_error()].
+    /// The codes are available as constants on the session, like `ERR_REPLAY_FILE` or `ERR_NO_CALLBACK_NODE`.
+    #[export]
+    pub fn get_last_error_code(&mut self, _owner: &Node) -> i64 {
+        error::last_error_code(&self.last_error)
+    }
+
+    /// Returns the message of the last error that occurred, or an empty String if no error occurred since [Self::clear_last_error()].
+    #[export]
+    pub fn get_last_error(&mut self, _owner: &Node) -> String {
+        self.last_error
+            .as_ref()
+            .map_or_else(String::new, |e| e.to_string())
+    }
+
+    /// Clears the last error, after which [Self::get_last_error_code()] returns `ERR_OK` until another error occurs.
+    #[export]
+    pub fn clear_last_error(&mut self, _owner: &Node) {
+        self.last_error = None;
+    }
+
+    /// Returns statistics about the requests handled by this session as a `Dictionary`, see [SessionMetrics].
+    /// Every seek backwards is counted as a rollback.
+    #[export]
+    pub fn get_session_metrics(&mut self, _owner: &Node) -> Dictionary {
+        ggrs_request_handlers::session_metrics_to_dictionary(&self.metrics).into_shared()
+    }
+
+    /// Resets the statistics returned by [Self::get_session_metrics()].
+    #[export]
+    pub fn reset_session_metrics(&mut self, _owner: &Node) {
+        self.metrics.reset();
+    }
+
+    //NON-EXPORTED FUNCTIONS
+    fn step_playback(&mut self) -> PlaybackStatus {
+        if !self.can_play() {
+            return PlaybackStatus::Failed;
+        }
+        let requests = match self.playback.as_mut().and_then(ReplayPlayback::advance) {
+            Some(requests) => requests,
+            None => return PlaybackStatus::Finished,
+        };
+        self.handle_requests(requests);
+        PlaybackStatus::Advanced
+    }
+
+    /// Reports an error and returns false when no replay was loaded or no callback nodes were set.
+    fn can_play(&mut self) -> bool {
+        if self.playback.is_none() {
+            self.report(GodotGGRSError::ReplayFile(
+                ERR_MESSAGE_NO_REPLAY_LOADED.to_owned(),
+            ));
+            return false;
+        }
+        if self.callback_node.is_none() && self.rollback_participants.is_empty() {
+            self.report(GodotGGRSError::NoCallbackNode);
+            return false;
+        }
+        true
+    }
+
+    fn handle_requests(&mut self, requests: Vec<GGRSRequest>) {
+        if let Some(callback_nodes) = ggrs_request_handlers::CallbackNodes::new(
+            &self.callback_node,
+            &self.rollback_participants,
+        ) {
+            rollback_callbacks::handle_requests(
+                &mut GodotCallbacks::new(
+                    callback_nodes,
+                    self.input_schema.as_ref(),
+                    self.resimulation_flag,
+                ),
+                requests,
+                InputConfirmation::ALL_CONFIRMED,
+                &mut self.metrics,
+            );
+        }
+    }
+
+    fn report(&mut self, error: GodotGGRSError) {
+        godot_error!("{}", error);
+        self.last_error = Some(error);
+    }
+}
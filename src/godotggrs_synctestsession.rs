@@ -1,12 +1,33 @@
+use crate::error::{self, GodotGGRSError};
+use crate::fuzzing;
+use crate::ggrs_request_handlers::{CallbackNodes, GodotCallbacks};
+use crate::input_schema::InputSchema;
+use crate::mismatch_capture::{RecordingCallbacks, StateHistory, StateMismatch};
+use crate::rollback_callbacks::{self, InputConfirmation};
+use crate::session_metrics::SessionMetrics;
 use crate::*;
-use ggrs::{PlayerHandle, SyncTestSession};
+use ggrs::{Frame, GGRSError, PlayerHandle, SyncTestSession};
 
 /// A Godot implementation of [`SyncTestSession`]
+///
+/// Mismatches can be investigated by enabling desync hunting through [Self::set_desync_hunting()], which keeps the states of the frame of which the checksums mismatched.
+/// # Errors
+/// Failing functions print an error and record an error code, which can be retrieved with [Self::get_last_error_code()].
+/// All codes are exposed as integer constants on the node, like `ERR_NO_SESSION` or `ERR_NOT_SYNCHRONIZED`.
 #[derive(NativeClass)]
 #[inherit(Node)]
+#[register_with(Self::register)]
 pub struct GodotGGRSSyncTestSession {
     sess: Option<SyncTestSession>,
     callback_node: Option<Ref<Node>>,
+    rollback_participants: Vec<Ref<Node>>,
+    input_schema: Option<InputSchema>,
+    resimulation_flag: bool,
+    last_error: Option<GodotGGRSError>,
+    metrics: SessionMetrics,
+    desync_hunting: bool,
+    state_history: StateHistory,
+    last_mismatch: Option<StateMismatch>,
 }
 
 impl GodotGGRSSyncTestSession {
@@ -14,8 +35,20 @@ impl GodotGGRSSyncTestSession {
         GodotGGRSSyncTestSession {
             sess: None,
             callback_node: None,
+            rollback_participants: Vec::new(),
+            input_schema: None,
+            resimulation_flag: false,
+            last_error: None,
+            metrics: SessionMetrics::new(),
+            desync_hunting: false,
+            state_history: StateHistory::new(),
+            last_mismatch: None,
         }
     }
+
+    fn register(builder: &ClassBuilder<Self>) {
+        error::register_error_codes(builder);
+    }
 }
 
 #[methods]
@@ -27,10 +60,13 @@ impl GodotGGRSSyncTestSession {
     }
 
     /// Creates a [SyncTestSession],
-    /// call this when you want to start setting up a `SyncTestSession` takes the total number of players, the check distance and the max prediction frames as parameters
+    /// call this when you want to start setting up a `SyncTestSession` takes the total number of players, the check distance, the max prediction frames and optionally the input size in bytes as parameters
     /// # Notes
     /// - Max prediction frames is the maximum number of frames GGRS will roll back. Every gamestate older than this is guaranteed to be correct if the players did not desync.
     /// - This value used to default to `8 frames`, but this has been made adjustable with `GGRS 0.7.0`
+    /// - When the input size is omitted or `0` it defaults to [DEFAULT_INPUT_SIZE], which allows inputs to be passed as ints through [Self::advance_frame()].
+    /// Any other input size requires inputs to be passed as `PoolByteArray`s through [Self::advance_frame_bytes()].
+    /// - When an input schema has been set through [Self::set_input_schema()] and the input size is omitted, the input size of the schema is used.
     #[export]
     pub fn create_new_session(
         &mut self,
@@ -38,11 +74,20 @@ impl GodotGGRSSyncTestSession {
         num_players: u32,
         check_distance: usize,
         max_pred: usize,
+        #[opt] input_size: usize,
     ) {
-        let input_size: usize = std::mem::size_of::<u32>();
+        let input_size = match &self.input_schema {
+            Some(schema) if input_size == 0 => schema.input_size(),
+            _ if input_size == 0 => DEFAULT_INPUT_SIZE,
+            _ => input_size,
+        };
         match SyncTestSession::new(num_players, input_size, max_pred, check_distance) {
-            Ok(s) => self.sess = Some(s),
-            Err(e) => godot_error!("{}", e),
+            Ok(s) => {
+                self.sess = Some(s);
+                self.state_history.clear();
+                self.last_mismatch = None;
+            }
+            Err(e) => self.report(e.into()),
         }
     }
 
@@ -50,7 +95,7 @@ impl GodotGGRSSyncTestSession {
     #[deprecated(since = "0.5.0", note = "please use `create_new_session()` instead")]
     #[export]
     pub fn create_session(&mut self, _owner: &Node, num_players: u32, check_distance: usize) {
-        self.create_new_session(_owner, num_players, check_distance, 8)
+        self.create_new_session(_owner, num_players, check_distance, 8, DEFAULT_INPUT_SIZE)
     }
 
     /// Sets [SyncTestSession::set_frame_delay()] of specified handle.
@@ -66,45 +111,183 @@ impl GodotGGRSSyncTestSession {
         match &mut self.sess {
             Some(s) => match s.set_frame_delay(frame_delay, player_handle) {
                 Ok(_) => return,
-                Err(e) => godot_error!("{}", e),
+                Err(e) => self.report(e.into()),
             },
-            None => godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE),
+            None => self.report(GodotGGRSError::NoSession),
         }
     }
 
-    /// This function will advance the frame using an array of all the inputs given as a parameter (inputs are currently an int in Godot).
-    /// Before using this function you have to set the callback node and make sure it has the following callback functions implemented
+    /// This function will advance the frame using an array of all the inputs given as a parameter (inputs are ints in Godot).
+    /// This only works when the session was created with an input size of [DEFAULT_INPUT_SIZE], use [Self::advance_frame_bytes()] for any other input size.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
     /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
     /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
-    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
     /// # Errors
     /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
-    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if a callback node has not been set
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if the input size of the session is not [DEFAULT_INPUT_SIZE]
     #[export]
     pub fn advance_frame(&mut self, _owner: &Node, all_inputs: Vec<u32>) {
         let mut all_inputs_bytes = Vec::new();
         for i in all_inputs {
             all_inputs_bytes.push(Vec::from(i.to_be_bytes()));
         }
+        self.advance_frame_with_bytes(all_inputs_bytes);
+    }
 
-        match self.callback_node {
-            Some(callback_node) => match &mut self.sess {
-                Some(s) => match s.advance_frame(&all_inputs_bytes) {
-                    Ok(requests) => {
-                        ggrs_request_handlers::handle_requests(&callback_node, requests);
-                    }
-                    Err(e) => {
-                        godot_error!("{}", e);
-                    }
-                },
-                None => {
-                    godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
-                }
-            },
+    /// This function will advance the frame using an array of all the inputs given as a parameter (inputs are `PoolByteArray`s in Godot).
+    /// The size of every `PoolByteArray` should match the input size the session was created with.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
+    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if the size of an input does not match the input size of the session
+    #[export]
+    pub fn advance_frame_bytes(&mut self, _owner: &Node, all_inputs: Vec<ByteArray>) {
+        let mut all_inputs_bytes = Vec::new();
+        for i in all_inputs {
+            all_inputs_bytes.push(i.read().to_vec());
+        }
+        self.advance_frame_with_bytes(all_inputs_bytes);
+    }
+
+    /// This function will advance the frame using an array of all the inputs given as a parameter (inputs are `Dictionary`s in Godot).
+    /// Every `Dictionary` is packed into an input buffer using the schema set with [Self::set_input_schema()], fields that are missing are packed as zero while unknown keys and values of the wrong type are errors.
+    /// Before using this function you have to set the callback node (or add rollback participants) and make sure it has the following callback functions implemented
+    /// - [CALLBACK_FUNC_SAVE_GAME_STATE]
+    /// - [CALLBACK_FUNC_LOAD_GAME_STATE]
+    /// - [CALLBACK_FUNC_ADVANCE_FRAME]
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print a [ERR_MESSAGE_NO_INPUT_SCHEMA] error if no input schema has been set
+    /// - Will print an error if a `Dictionary` could not be packed using the input schema, for example because it contains a key that is not part of the schema or a float instead of an int
+    #[export]
+    pub fn advance_frame_dict(&mut self, _owner: &Node, all_inputs: Vec<Dictionary>) {
+        let schema = match &self.input_schema {
+            Some(schema) => schema,
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_CALLBACK_NODE);
+                self.report(GodotGGRSError::NoInputSchema);
+                return;
+            }
+        };
+        let mut all_inputs_bytes = Vec::new();
+        for i in all_inputs {
+            match ggrs_request_handlers::dictionary_to_input(schema, &i) {
+                Ok(bytes) => all_inputs_bytes.push(bytes),
+                Err(e) => {
+                    self.report(e.into());
+                    return;
+                }
             }
         }
+        self.advance_frame_with_bytes(all_inputs_bytes);
+    }
+
+    /// Advances the session by the given amount of frames with random inputs for every player, generated from the seed, and stops at the first checksum mismatch.
+    /// Returns a `Dictionary` with the keys `passed`, `seed`, `frames_advanced` and `mismatch_frame`, which is `-1` when no mismatch was found.
+    /// Returns an empty `Dictionary` when the session could not be advanced at all.
+    /// # Notes
+    /// - The same seed always generates the same inputs, so a mismatch can be reproduced by fuzzing a new session with the reported seed.
+    /// - Inputs are random bytes, so every field of an input schema can receive any value that fits its type.
+    /// - Enable [Self::set_desync_hunting()] to keep the states of the frame of which the checksums mismatched.
+    /// # Example
+    /// ```gdscript
+    /// var result = sync_test.run_fuzz(1000, randi())
+    /// if not result["passed"]:
+    ///     print("Desync at frame %d, seed %d" % [result["mismatch_frame"], result["seed"]])
+    /// ```
+    /// # Errors
+    /// - Will print a [ERR_MESSAGE_NO_SESSION_MADE] error if a session has not been made
+    /// - Will print a [ERR_MESSAGE_NO_CALLBACK_NODE] error if neither a callback node nor rollback participants have been set
+    /// - Will print an error containing the seed and the frame when the checksums of a frame mismatched
+    #[export]
+    pub fn run_fuzz(&mut self, _owner: &Node, frames: u32, seed: i64) -> Dictionary {
+        let report = match (
+            CallbackNodes::new(&self.callback_node, &self.rollback_participants),
+            &mut self.sess,
+        ) {
+            (None, _) => {
+                self.report(GodotGGRSError::NoCallbackNode);
+                return Dictionary::new_shared();
+            }
+            (_, None) => {
+                self.report(GodotGGRSError::NoSession);
+                return Dictionary::new_shared();
+            }
+            (Some(callback_nodes), Some(s)) => {
+                //Godot ints are signed, so the seed is used as its bit pattern
+                let seed = seed as u64;
+                let mut callbacks = GodotCallbacks::new(
+                    callback_nodes,
+                    self.input_schema.as_ref(),
+                    self.resimulation_flag,
+                );
+                if self.desync_hunting {
+                    fuzzing::run_fuzz(
+                        s,
+                        &mut RecordingCallbacks::new(&mut callbacks, &mut self.state_history),
+                        frames,
+                        seed,
+                        &mut self.metrics,
+                    )
+                } else {
+                    fuzzing::run_fuzz(s, &mut callbacks, frames, seed, &mut self.metrics)
+                }
+            }
+        };
+        match &report.error {
+            Some(GGRSError::MismatchedChecksum { frame }) => {
+                godot_error!(
+                    "Fuzzing with seed {} found a checksum mismatch at frame {}.",
+                    report.seed as i64,
+                    frame
+                );
+                if self.desync_hunting {
+                    self.capture_mismatch(*frame);
+                }
+                self.report(GGRSError::MismatchedChecksum { frame: *frame }.into());
+            }
+            Some(e) => self.report(e.clone().into()),
+            None => (),
+        }
+        let result = Dictionary::new();
+        result.insert("passed", report.error.is_none());
+        result.insert("seed", report.seed as i64);
+        result.insert("frames_advanced", report.frames_advanced);
+        result.insert("mismatch_frame", report.mismatch_frame());
+        result.into_shared()
+    }
+
+    /// Sets the input schema used to pack a `Dictionary` into the input buffer, see [Self::advance_frame_dict()].
+    /// Once a schema is set the inputs given to [CALLBACK_FUNC_ADVANCE_FRAME] will be a `Dictionary` as well.
+    /// The schema is a `Dictionary` mapping field names to field types, fields are packed in the order they are inserted.
+    /// Supported field types are `"bool"`, `"i8"`, `"u8"`, `"i16"`, `"u16"`, `"i32"`, `"u32"` and `"bits(n)"` for an unsigned integer of `n` bits.
+    /// # Errors
+    /// - Will print an error if the schema could not be parsed
+    /// - Will print a [ERR_MESSAGE_INPUT_SIZE_MISMATCH] error if a session has been made with an input size that does not match the schema
+    #[export]
+    pub fn set_input_schema(&mut self, _owner: &Node, schema: Dictionary) {
+        match ggrs_request_handlers::dictionary_to_input_schema(&schema) {
+            Ok(schema) => match &self.sess {
+                Some(s) if s.input_size() != schema.input_size() => {
+                    self.report(GodotGGRSError::BadInputSize)
+                }
+                _ => self.input_schema = Some(schema),
+            },
+            Err(e) => self.report(e.into()),
+        }
+    }
+
+    /// Enables passing a second argument to [CALLBACK_FUNC_ADVANCE_FRAME], which is true when the frame is re-simulated after a rollback.
+    /// See [GodotGGRSP2PSession::set_resimulation_flag()], this should be enabled whenever the callback declares the second parameter.
+    #[export]
+    pub fn set_resimulation_flag(&mut self, _owner: &Node, enabled: bool) {
+        self.resimulation_flag = enabled;
     }
 
     /// Calls and returns [SyncTestSession::max_prediction()].
@@ -116,15 +299,197 @@ impl GodotGGRSSyncTestSession {
         match &mut self.sess {
             Some(s) => s.max_prediction(),
             None => {
-                godot_error!("{}", ERR_MESSAGE_NO_SESSION_MADE);
+                self.report(GodotGGRSError::NoSession);
                 return 0;
             }
         }
     }
 
+    /// Enables or disables desync hunting, which keeps the states saved during recent frames so the states of a frame of which the checksums mismatched can be compared.
+    /// When a mismatch is found, the byte offsets at which the original and the re-simulated state differ are printed and both states can be retrieved with [Self::get_last_mismatch()].
+    /// # Notes
+    /// - When the callback node or a rollback participant implements [CALLBACK_FUNC_DESCRIBE_STATE], the fields that differ are printed as well.
+    /// The function takes a state as a `PoolByteArray` and should return a `Dictionary` mapping field names to values.
+    /// - Every saved state is copied, so only enable this while hunting a desync.
+    #[export]
+    pub fn set_desync_hunting(&mut self, _owner: &Node, enabled: bool) {
+        self.desync_hunting = enabled;
+        self.state_history.clear();
+    }
+
+    /// Returns the states of the last frame of which the checksums mismatched while desync hunting was enabled, or an empty `Dictionary` if no mismatch was captured.
+    /// Contains the keys `frame`, `original_state` and `resimulated_state` as `PoolByteArray`s and `differing_offsets`, an `Array` of the first byte offsets at which the states differ.
+    #[export]
+    pub fn get_last_mismatch(&mut self, _owner: &Node) -> Dictionary {
+        let result = Dictionary::new();
+        if let Some(mismatch) = &self.last_mismatch {
+            let differing_offsets = mismatch
+                .differing_offsets
+                .iter()
+                .map(|offset| *offset as i64)
+                .collect::<Vec<_>>();
+            result.insert("frame", mismatch.frame);
+            result.insert(
+                "original_state",
+                ByteArray::from_vec(mismatch.original_state.clone()),
+            );
+            result.insert(
+                "resimulated_state",
+                ByteArray::from_vec(mismatch.resimulated_state.clone()),
+            );
+            result.insert("differing_offsets", differing_offsets);
+        }
+        result.into_shared()
+    }
+
     /// Sets the callback node that will be called when using [Self::advance_frame()]
     #[export]
     pub fn set_callback_node(&mut self, _owner: &Node, callback: Ref<Node>) {
         self.callback_node = Some(callback);
     }
+
+    /// Adds a rollback participant that will be called when using [Self::advance_frame()], this is an alternative to using a single callback node.
+    /// Every participant implements the callback functions itself and saves and loads its own slice of the game state.
+    /// The slices are concatenated into a single state buffer in the order the participants were added, so participants should not change while the session is running.
+    /// Once any participant has been added the callback node is no longer called.
+    /// # Example
+    /// ```gdscript
+    /// for node in get_tree().get_nodes_in_group("rollback"):
+    ///     sync_test.add_rollback_participant(node)
+    /// ```
+    #[export]
+    pub fn add_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        if !self.rollback_participants.contains(&participant) {
+            self.rollback_participants.push(participant);
+        }
+    }
+
+    /// Removes a rollback participant that was added with [Self::add_rollback_participant()].
+    #[export]
+    pub fn remove_rollback_participant(&mut self, _owner: &Node, participant: Ref<Node>) {
+        self.rollback_participants.retain(|p| *p != participant);
+    }
+
+    /// Removes all rollback participants, after which the callback node will be called again.
+    #[export]
+    pub fn clear_rollback_participants(&mut self, _owner: &Node) {
+        self.rollback_participants.clear();
+    }
+
+    /// Returns the code of the last error that occurred, or `ERR_OK` if no error occurred since [Self::clear_last_error()].
+    /// The codes are available as constants on the session, like `ERR_NO_SESSION` or `ERR_INVALID_HANDLE`.
+    #[export]
+    pub fn get_last_error_code(&mut self, _owner: &Node) -> i64 {
+        error::last_error_code(&self.last_error)
+    }
+
+    /// Returns the message of the last error that occurred, or an empty String if no error occurred since [Self::clear_last_error()].
+    #[export]
+    pub fn get_last_error(&mut self, _owner: &Node) -> String {
+        self.last_error
+            .as_ref()
+            .map_or_else(String::new, |e| e.to_string())
+    }
+
+    /// Clears the last error, after which [Self::get_last_error_code()] returns `ERR_OK` until another error occurs.
+    #[export]
+    pub fn clear_last_error(&mut self, _owner: &Node) {
+        self.last_error = None;
+    }
+
+    /// Returns statistics about the requests handled by this session as a `Dictionary`, see [SessionMetrics].
+    /// Contains the keys `load_count`, `save_count`, `advance_count`, `resimulated_frames`, `max_rollback_depth`, `rollback_depths` (a `Dictionary` mapping a rollback depth to how often it happened)
+    /// and the average and peak time spent in each callback in microseconds, like `average_advance_usec` and `peak_load_usec`.
+    #[export]
+    pub fn get_session_metrics(&mut self, _owner: &Node) -> Dictionary {
+        ggrs_request_handlers::session_metrics_to_dictionary(&self.metrics).into_shared()
+    }
+
+    /// Resets the statistics returned by [Self::get_session_metrics()].
+    #[export]
+    pub fn reset_session_metrics(&mut self, _owner: &Node) {
+        self.metrics.reset();
+    }
+
+    //NON-EXPORTED FUNCTIONS
+    fn advance_frame_with_bytes(&mut self, all_inputs: Vec<Vec<u8>>) {
+        match ggrs_request_handlers::CallbackNodes::new(
+            &self.callback_node,
+            &self.rollback_participants,
+        ) {
+            Some(callback_nodes) => match &mut self.sess {
+                Some(s) if all_inputs.iter().any(|i| i.len() != s.input_size()) => {
+                    self.report(GodotGGRSError::BadInputSize);
+                }
+                Some(s) => match s.advance_frame(&all_inputs) {
+                    Ok(requests) => {
+                        let mut callbacks = GodotCallbacks::new(
+                            callback_nodes,
+                            self.input_schema.as_ref(),
+                            self.resimulation_flag,
+                        );
+                        if self.desync_hunting {
+                            rollback_callbacks::handle_requests(
+                                &mut RecordingCallbacks::new(
+                                    &mut callbacks,
+                                    &mut self.state_history,
+                                ),
+                                requests,
+                                InputConfirmation::ALL_CONFIRMED,
+                                &mut self.metrics,
+                            );
+                        } else {
+                            rollback_callbacks::handle_requests(
+                                &mut callbacks,
+                                requests,
+                                InputConfirmation::ALL_CONFIRMED,
+                                &mut self.metrics,
+                            );
+                        }
+                    }
+                    Err(GGRSError::MismatchedChecksum { frame }) if self.desync_hunting => {
+                        self.capture_mismatch(frame);
+                        self.report(GGRSError::MismatchedChecksum { frame }.into());
+                    }
+                    Err(e) => {
+                        self.report(e.into());
+                    }
+                },
+                None => {
+                    self.report(GodotGGRSError::NoSession);
+                }
+            },
+            None => {
+                self.report(GodotGGRSError::NoCallbackNode);
+            }
+        }
+    }
+
+    /// Keeps the states of the frame of which the checksums mismatched and prints where they differ.
+    fn capture_mismatch(&mut self, frame: Frame) {
+        let mismatch = match self.state_history.mismatch(frame) {
+            Some(mismatch) => mismatch,
+            None => return,
+        };
+        godot_error!(
+            "The states of frame {} differ at byte offsets {:?}.",
+            frame,
+            mismatch.differing_offsets
+        );
+        if let Some(callback_nodes) =
+            CallbackNodes::new(&self.callback_node, &self.rollback_participants)
+        {
+            for difference in
+                ggrs_request_handlers::describe_state_mismatch(callback_nodes, &mismatch)
+            {
+                godot_error!("{}", difference);
+            }
+        }
+        self.last_mismatch = Some(mismatch);
+    }
+
+    fn report(&mut self, error: GodotGGRSError) {
+        godot_error!("{}", error);
+        self.last_error = Some(error);
+    }
 }
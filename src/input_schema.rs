@@ -0,0 +1,332 @@
+use std::fmt;
+
+/// The type of a single field inside an [InputSchema].
+/// In Godot a field type is written as a String, for example `"bool"`, `"i8"`, `"u16"` or `"bits(12)"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFieldType {
+    /// A single bit that is passed to Godot as a `bool`.
+    Bool,
+    /// An unsigned integer with the given amount of bits, ranging from 1 to 32 bits.
+    Bits(u32),
+    /// A signed 8-bit integer.
+    I8,
+    /// An unsigned 8-bit integer.
+    U8,
+    /// A signed 16-bit integer.
+    I16,
+    /// An unsigned 16-bit integer.
+    U16,
+    /// A signed 32-bit integer.
+    I32,
+    /// An unsigned 32-bit integer.
+    U32,
+}
+
+impl InputFieldType {
+    /// Parses a field type from its String representation.
+    pub fn parse(field_type: &str) -> Result<Self, InputSchemaError> {
+        let field_type = field_type.trim();
+        match field_type {
+            "bool" => Ok(InputFieldType::Bool),
+            "i8" => Ok(InputFieldType::I8),
+            "u8" => Ok(InputFieldType::U8),
+            "i16" => Ok(InputFieldType::I16),
+            "u16" => Ok(InputFieldType::U16),
+            "i32" => Ok(InputFieldType::I32),
+            "u32" => Ok(InputFieldType::U32),
+            _ => {
+                let bits = field_type
+                    .strip_prefix("bits(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .and_then(|width| width.trim().parse::<u32>().ok());
+                match bits {
+                    Some(width) if (1..=32).contains(&width) => Ok(InputFieldType::Bits(width)),
+                    _ => Err(InputSchemaError::UnknownFieldType(field_type.to_owned())),
+                }
+            }
+        }
+    }
+
+    /// Returns the amount of bits this field takes up inside the input buffer.
+    pub fn bit_width(&self) -> u32 {
+        match self {
+            InputFieldType::Bool => 1,
+            InputFieldType::Bits(width) => *width,
+            InputFieldType::I8 | InputFieldType::U8 => 8,
+            InputFieldType::I16 | InputFieldType::U16 => 16,
+            InputFieldType::I32 | InputFieldType::U32 => 32,
+        }
+    }
+
+    /// Returns true if values of this field are two's complement signed integers.
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            InputFieldType::I8 | InputFieldType::I16 | InputFieldType::I32
+        )
+    }
+
+    fn value_range(&self) -> (i64, i64) {
+        let width = self.bit_width();
+        if self.is_signed() {
+            (-(1i64 << (width - 1)), (1i64 << (width - 1)) - 1)
+        } else {
+            (0, (1i64 << width) - 1)
+        }
+    }
+}
+
+/// A single named field inside an [InputSchema].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputField {
+    /// The name of the field, used as the key of the Godot `Dictionary`.
+    pub name: String,
+    /// The type of the field.
+    pub field_type: InputFieldType,
+}
+
+/// Errors that can occur while building an [InputSchema] or packing inputs with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSchemaError {
+    /// The field type String could not be parsed.
+    UnknownFieldType(String),
+    /// A field with the same name was already added to the schema.
+    DuplicateField(String),
+    /// The schema does not contain any fields.
+    Empty,
+    /// The amount of values given does not match the amount of fields in the schema.
+    ValueCountMismatch { expected: usize, actual: usize },
+    /// The value of a field does not fit inside the bits of the field.
+    ValueOutOfRange { field: String, value: i64 },
+    /// The value of a field is neither an int nor a bool, depending on what the field expects.
+    WrongValueType { field: String, expected: String },
+    /// The input contains a field that is not part of the schema, which is usually a misspelled name.
+    UnknownField(String),
+}
+
+impl fmt::Display for InputSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputSchemaError::UnknownFieldType(field_type) => {
+                write!(f, "Unknown input field type \"{}\".", field_type)
+            }
+            InputSchemaError::DuplicateField(name) => {
+                write!(f, "Input field \"{}\" was defined more than once.", name)
+            }
+            InputSchemaError::Empty => write!(f, "The input schema does not contain any fields."),
+            InputSchemaError::ValueCountMismatch { expected, actual } => write!(
+                f,
+                "Expected {} input values but received {}.",
+                expected, actual
+            ),
+            InputSchemaError::ValueOutOfRange { field, value } => write!(
+                f,
+                "Value {} does not fit inside input field \"{}\".",
+                value, field
+            ),
+            InputSchemaError::WrongValueType { field, expected } => write!(
+                f,
+                "Input field \"{}\" expects a value of type {}.",
+                field, expected
+            ),
+            InputSchemaError::UnknownField(name) => {
+                write!(
+                    f,
+                    "Input field \"{}\" is not part of the input schema.",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputSchemaError {}
+
+/// Describes how named input fields are packed into the input buffer of a session.
+/// Fields are packed in the order they were added, starting at the least significant bit of the first byte.
+/// Packing is done bit by bit so the resulting buffer is identical on every platform.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputSchema {
+    fields: Vec<InputField>,
+}
+
+impl InputSchema {
+    /// Creates an empty [InputSchema].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a field to the end of the schema.
+    pub fn add_field(
+        &mut self,
+        name: &str,
+        field_type: InputFieldType,
+    ) -> Result<(), InputSchemaError> {
+        if self.fields.iter().any(|f| f.name == name) {
+            return Err(InputSchemaError::DuplicateField(name.to_owned()));
+        }
+        self.fields.push(InputField {
+            name: name.to_owned(),
+            field_type,
+        });
+        Ok(())
+    }
+
+    /// Returns all fields of the schema in packing order.
+    pub fn fields(&self) -> &[InputField] {
+        &self.fields
+    }
+
+    /// Returns the total amount of bits used by all fields.
+    pub fn bit_size(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|f| f.field_type.bit_width() as usize)
+            .sum()
+    }
+
+    /// Returns the amount of bytes needed to store all fields, this is the input size of the session.
+    pub fn input_size(&self) -> usize {
+        self.bit_size().div_ceil(8)
+    }
+
+    /// Packs the values of all fields (in schema order) into an input buffer.
+    pub fn pack(&self, values: &[i64]) -> Result<Vec<u8>, InputSchemaError> {
+        if values.len() != self.fields.len() {
+            return Err(InputSchemaError::ValueCountMismatch {
+                expected: self.fields.len(),
+                actual: values.len(),
+            });
+        }
+
+        let mut buffer = vec![0u8; self.input_size()];
+        let mut bit_offset = 0;
+        for (field, value) in self.fields.iter().zip(values) {
+            let (min, max) = field.field_type.value_range();
+            if *value < min || *value > max {
+                return Err(InputSchemaError::ValueOutOfRange {
+                    field: field.name.clone(),
+                    value: *value,
+                });
+            }
+
+            let raw = *value as u64;
+            for bit in 0..field.field_type.bit_width() as usize {
+                if raw & (1 << bit) != 0 {
+                    let position = bit_offset + bit;
+                    buffer[position / 8] |= 1 << (position % 8);
+                }
+            }
+            bit_offset += field.field_type.bit_width() as usize;
+        }
+        Ok(buffer)
+    }
+
+    /// Unpacks an input buffer into the values of all fields (in schema order).
+    /// Bits that are missing from the buffer are read as zero.
+    pub fn unpack(&self, buffer: &[u8]) -> Vec<i64> {
+        let mut values = Vec::with_capacity(self.fields.len());
+        let mut bit_offset = 0;
+        for field in &self.fields {
+            let width = field.field_type.bit_width() as usize;
+            let mut raw: u64 = 0;
+            for bit in 0..width {
+                let position = bit_offset + bit;
+                let is_set = buffer
+                    .get(position / 8)
+                    .is_some_and(|byte| byte & (1 << (position % 8)) != 0);
+                if is_set {
+                    raw |= 1 << bit;
+                }
+            }
+            bit_offset += width;
+
+            // sign extend signed fields
+            let value = if field.field_type.is_signed() && raw & (1 << (width - 1)) != 0 {
+                (raw | (u64::MAX << width)) as i64
+            } else {
+                raw as i64
+            };
+            values.push(value);
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(fields: &[(&str, &str)]) -> InputSchema {
+        let mut schema = InputSchema::new();
+        for (name, field_type) in fields {
+            schema
+                .add_field(name, InputFieldType::parse(field_type).unwrap())
+                .unwrap();
+        }
+        schema
+    }
+
+    #[test]
+    fn packs_fields_lsb_first_across_byte_boundaries() {
+        let schema = schema(&[("buttons", "bits(12)"), ("stick_x", "i8")]);
+        assert_eq!(schema.input_size(), 3);
+        assert_eq!(schema.pack(&[0xABC, -1]), Ok(vec![0xBC, 0xFA, 0x0F]));
+        assert_eq!(schema.unpack(&[0xBC, 0xFA, 0x0F]), vec![0xABC, -1]);
+    }
+
+    #[test]
+    fn sign_extends_signed_fields() {
+        let schema = schema(&[("x", "i8"), ("y", "i16"), ("z", "u8")]);
+        assert_eq!(
+            schema.unpack(&[0x80, 0xFE, 0xFF, 0xFF]),
+            vec![-128, -2, 255]
+        );
+    }
+
+    #[test]
+    fn packs_bools_and_full_width_fields() {
+        let schema = schema(&[("jump", "bool"), ("frame", "u32")]);
+        let buffer = schema.pack(&[1, u32::MAX as i64]).unwrap();
+        assert_eq!(buffer, vec![0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+        assert_eq!(schema.unpack(&buffer), vec![1, u32::MAX as i64]);
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_fit() {
+        let schema = schema(&[("buttons", "bits(4)"), ("stick_x", "i8")]);
+        assert_eq!(
+            schema.pack(&[16, 0]),
+            Err(InputSchemaError::ValueOutOfRange {
+                field: "buttons".to_owned(),
+                value: 16
+            })
+        );
+        assert!(schema.pack(&[0, -129]).is_err());
+        assert!(schema.pack(&[0, 128]).is_err());
+        assert!(schema.pack(&[-1, 0]).is_err());
+        assert_eq!(
+            schema.pack(&[0]),
+            Err(InputSchemaError::ValueCountMismatch {
+                expected: 2,
+                actual: 1
+            })
+        );
+    }
+
+    #[test]
+    fn unpacks_what_was_packed() {
+        let schema = schema(&[
+            ("jump", "bool"),
+            ("buttons", "bits(11)"),
+            ("stick_x", "i8"),
+            ("stick_y", "i16"),
+            ("aim", "u16"),
+            ("seed", "i32"),
+        ]);
+        let values = vec![1, 0x5A5, -100, -30000, 65535, i32::MIN as i64];
+        let buffer = schema.pack(&values).unwrap();
+        assert_eq!(buffer.len(), schema.input_size());
+        assert_eq!(schema.unpack(&buffer), values);
+    }
+}
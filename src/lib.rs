@@ -8,23 +8,86 @@
 use gdnative::prelude::*;
 pub use godotggrs_p2psession::GodotGGRSP2PSession;
 pub use godotggrs_p2pspectatorsession::GodotGGRSP2PSpectatorSession;
+pub use godotggrs_replaysession::GodotGGRSReplaySession;
 pub use godotggrs_synctestsession::GodotGGRSSyncTestSession;
 
+mod address;
+pub mod desync_detection;
+mod error;
+mod frame_delay_tuning;
+mod fuzzing;
+mod ggrs_event_handlers;
 mod ggrs_request_handlers;
+mod godot_transport;
 mod godotggrs_p2psession;
 mod godotggrs_p2pspectatorsession;
+mod godotggrs_replaysession;
 mod godotggrs_synctestsession;
+mod input_schema;
+mod mismatch_capture;
+pub mod network_conditioner;
+mod rematch;
+pub mod replay;
+pub mod rollback_callbacks;
+pub mod session_metrics;
+pub mod transport;
+pub mod verification;
+pub mod wrapper_socket;
+mod xorshift;
 
 /// Error message that is printed when there's no GGRS session made.
 pub const ERR_MESSAGE_NO_SESSION_MADE: &str = "No session was made.";
-/// Error message that is printed when there's no callback node specified.
-pub const ERR_MESSAGE_NO_CALLBACK_NODE: &str = "No callback node was specified.";
+/// Error message that is printed when there's no callback node or rollback participant specified.
+pub const ERR_MESSAGE_NO_CALLBACK_NODE: &str =
+    "No callback node or rollback participants were specified.";
+/// Error message that is printed when the given input does not match the input size of the session.
+pub const ERR_MESSAGE_INPUT_SIZE_MISMATCH: &str =
+    "The given input does not match the input size of the session.";
+/// Error message that is printed when inputs are given as a `Dictionary` but no input schema was set.
+pub const ERR_MESSAGE_NO_INPUT_SCHEMA: &str = "No input schema was set.";
+/// Error message that is printed when an unknown address preference is given.
+pub const ERR_MESSAGE_INVALID_ADDRESS_PREFERENCE: &str =
+    "Unknown address preference, expected \"ipv4\", \"ipv6\" or \"any\".";
+/// Error message that is printed when a replay session is used before a replay was loaded.
+pub const ERR_MESSAGE_NO_REPLAY_LOADED: &str = "No replay was loaded.";
+/// The handle that is returned when a player could not be added to the session.
+pub const INVALID_PLAYER_HANDLE: i64 = -1;
+/// The input size that is used when no input size is given while creating a session, this is the size of an unsigned 32-bit integer.
+pub const DEFAULT_INPUT_SIZE: usize = std::mem::size_of::<u32>();
 /// The name of the Godot callback function that gets called when requesting a state save.
 pub const CALLBACK_FUNC_SAVE_GAME_STATE: &str = "ggrs_save_game_state";
 /// The name of the Godot callback function that gets called when requesting a state load.
 pub const CALLBACK_FUNC_LOAD_GAME_STATE: &str = "ggrs_load_game_state";
 /// The name of the Godot callback function that gets called when requesting to advance the frame.
 pub const CALLBACK_FUNC_ADVANCE_FRAME: &str = "ggrs_advance_frame";
+/// The name of the optional Godot callback function that gets called instead of `ggrs_advance_frame` when a frame is re-simulated after a rollback.
+pub const CALLBACK_FUNC_ADVANCE_FRAME_RESIM: &str = "ggrs_advance_frame_resim";
+/// The name of the optional Godot callback function that describes a saved state for a sync test mismatch, it takes the state as a `PoolByteArray` and should return a `Dictionary` of its fields.
+pub const CALLBACK_FUNC_DESCRIBE_STATE: &str = "ggrs_describe_state";
+/// The name of the function of a transport node that sends a datagram, it takes the address of the peer as a `String` and the datagram as a `PoolByteArray`.
+pub const TRANSPORT_FUNC_SEND_TO: &str = "send_to";
+/// The name of the function of a transport node that returns the datagrams received since the last call, as an `Array` of `[address, PoolByteArray]` pairs.
+pub const TRANSPORT_FUNC_POLL: &str = "poll";
+/// The name of the signal that is emitted when GGRS recommends skipping frames, has the argument `skip_frames`.
+pub const SIGNAL_WAIT_RECOMMENDATION: &str = "wait_recommendation";
+/// The name of the signal that is emitted when the connection to a remote client is interrupted, has the arguments `handle` and `timeout_ms`.
+pub const SIGNAL_NETWORK_INTERRUPTED: &str = "network_interrupted";
+/// The name of the signal that is emitted when the connection to a remote client has resumed, has the argument `handle`.
+pub const SIGNAL_NETWORK_RESUMED: &str = "network_resumed";
+/// The name of the signal that is emitted when a remote client has disconnected, has the argument `handle`.
+pub const SIGNAL_DISCONNECTED: &str = "disconnected";
+/// The name of the signal that is emitted when synchronizing with a remote client makes progress, has the arguments `handle`, `count` and `total`.
+pub const SIGNAL_SYNCHRONIZING: &str = "synchronizing";
+/// The name of the signal that is emitted when the session is synchronized with a remote client, has the argument `handle`.
+pub const SIGNAL_SYNCHRONIZED: &str = "synchronized";
+/// Name of the signal that is periodically emitted with the network stats of every remote handle, see `set_network_stats_interval()`.
+pub const SIGNAL_NETWORK_STATS_UPDATED: &str = "network_stats_updated";
+/// Name of the signal that is emitted when the checksum of a confirmed frame differs from the checksum reported by a remote player.
+pub const SIGNAL_DESYNC_DETECTED: &str = "desync_detected";
+/// Name of the signal that is emitted when a session has been closed, either by `close_session()`, by `reset_for_rematch()` or by creating a new session.
+pub const SIGNAL_SESSION_CLOSED: &str = "session_closed";
+/// Name of the signal that is emitted when `reset_for_rematch()` applies a recommended frame delay to a local player, has the arguments `handle`, `old_frame_delay` and `new_frame_delay`.
+pub const SIGNAL_FRAME_DELAY_CHANGED: &str = "frame_delay_changed";
 
 /// Routes all Rust panics to Godot so that any uncaught errors are still visible in Godot.
 pub fn init_panic_hook() {
@@ -71,6 +134,7 @@ fn init(handle: InitHandle) {
     handle.add_class::<GodotGGRSP2PSession>();
     handle.add_class::<GodotGGRSSyncTestSession>();
     handle.add_class::<GodotGGRSP2PSpectatorSession>();
+    handle.add_class::<GodotGGRSReplaySession>();
     init_panic_hook()
 }
 
@@ -0,0 +1,201 @@
+//! Keeping the states saved during a sync test, so the states of a frame of which the checksums mismatched can be compared.
+
+use crate::rollback_callbacks::{InputStatus, RollbackCallbacks};
+use ggrs::{Frame, GameInput};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+/// The amount of frames of which saved states are kept, counting back from the newest saved frame.
+const MAX_STATE_HISTORY: Frame = 128;
+/// The maximum amount of differing byte offsets that is reported for a mismatch.
+pub const MAX_DIFFERING_OFFSETS: usize = 16;
+
+/// The states of a frame that were saved when it was first simulated and when it was re-simulated after a rollback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateMismatch {
+    /// The frame of which the checksums mismatched.
+    pub frame: Frame,
+    /// The state that was saved when the frame was first simulated.
+    pub original_state: Vec<u8>,
+    /// The state that was saved when the frame was re-simulated.
+    pub resimulated_state: Vec<u8>,
+    /// The first offsets at which the states differ, at most [MAX_DIFFERING_OFFSETS].
+    pub differing_offsets: Vec<usize>,
+}
+
+/// Keeps the first and the latest saved state of recent frames.
+#[derive(Debug, Clone, Default)]
+pub struct StateHistory {
+    original_states: BTreeMap<Frame, Vec<u8>>,
+    resimulated_states: BTreeMap<Frame, Vec<u8>>,
+}
+
+impl StateHistory {
+    /// Creates an empty [StateHistory].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a saved state, states that are saved again for the same frame are kept as re-simulated states.
+    pub fn record(&mut self, frame: Frame, state: &[u8]) {
+        match self.original_states.entry(frame) {
+            Entry::Occupied(_) => {
+                self.resimulated_states.insert(frame, state.to_vec());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(state.to_vec());
+            }
+        }
+        let oldest_frame = frame - MAX_STATE_HISTORY;
+        self.original_states.retain(|f, _| *f > oldest_frame);
+        self.resimulated_states.retain(|f, _| *f > oldest_frame);
+    }
+
+    /// Returns the original and re-simulated state of the frame, if both were recorded.
+    pub fn mismatch(&self, frame: Frame) -> Option<StateMismatch> {
+        let original_state = self.original_states.get(&frame)?;
+        let resimulated_state = self.resimulated_states.get(&frame)?;
+        Some(StateMismatch {
+            frame,
+            original_state: original_state.clone(),
+            resimulated_state: resimulated_state.clone(),
+            differing_offsets: differing_offsets(original_state, resimulated_state),
+        })
+    }
+
+    /// Discards all recorded states.
+    pub fn clear(&mut self) {
+        self.original_states.clear();
+        self.resimulated_states.clear();
+    }
+}
+
+/// Returns the first offsets at which two buffers differ, bytes past the end of the shorter buffer are different.
+pub fn differing_offsets(a: &[u8], b: &[u8]) -> Vec<usize> {
+    (0..a.len().max(b.len()))
+        .filter(|offset| a.get(*offset) != b.get(*offset))
+        .take(MAX_DIFFERING_OFFSETS)
+        .collect()
+}
+
+/// Wraps other callbacks and records every state they save in a [StateHistory].
+pub struct RecordingCallbacks<'a, C> {
+    callbacks: &'a mut C,
+    history: &'a mut StateHistory,
+}
+
+impl<'a, C: RollbackCallbacks> RecordingCallbacks<'a, C> {
+    /// Wraps the callbacks, saved states are recorded in the given history.
+    pub fn new(callbacks: &'a mut C, history: &'a mut StateHistory) -> Self {
+        RecordingCallbacks { callbacks, history }
+    }
+}
+
+impl<C: RollbackCallbacks> RollbackCallbacks for RecordingCallbacks<'_, C> {
+    fn save_game_state(&mut self, frame: Frame) -> (Vec<u8>, u64) {
+        let (state, checksum) = self.callbacks.save_game_state(frame);
+        self.history.record(frame, &state);
+        (state, checksum)
+    }
+
+    fn load_game_state(&mut self, frame: Frame, state: Vec<u8>, checksum: u64) {
+        self.callbacks.load_game_state(frame, state, checksum)
+    }
+
+    fn advance_frame(
+        &mut self,
+        inputs: Vec<GameInput>,
+        statuses: &[InputStatus],
+        resimulating: bool,
+    ) {
+        self.callbacks.advance_frame(inputs, statuses, resimulating)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rollback_callbacks::{self, InputConfirmation};
+    use crate::session_metrics::SessionMetrics;
+    use ggrs::{GGRSError, SyncTestSession};
+
+    /// A game of which the state is the amount of advanced frames, which goes wrong once a frame has been re-simulated.
+    #[derive(Default)]
+    struct FlakyGame {
+        frame: u32,
+        loaded: bool,
+    }
+
+    impl RollbackCallbacks for FlakyGame {
+        fn save_game_state(&mut self, _frame: Frame) -> (Vec<u8>, u64) {
+            let state = self.frame.to_le_bytes().to_vec();
+            (state, self.frame as u64)
+        }
+
+        fn load_game_state(&mut self, _frame: Frame, state: Vec<u8>, _checksum: u64) {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&state);
+            self.frame = u32::from_le_bytes(bytes);
+            self.loaded = true;
+        }
+
+        fn advance_frame(&mut self, _inputs: Vec<GameInput>, _: &[InputStatus], _: bool) {
+            self.frame += if self.loaded { 2 } else { 1 };
+        }
+    }
+
+    #[test]
+    fn finds_the_first_differing_offsets() {
+        assert_eq!(
+            differing_offsets(&[1, 2, 3], &[1, 2, 3]),
+            Vec::<usize>::new()
+        );
+        assert_eq!(differing_offsets(&[1, 2, 3], &[1, 5, 3, 4]), vec![1, 3]);
+        assert_eq!(
+            differing_offsets(&[0; 40], &[1; 40]).len(),
+            MAX_DIFFERING_OFFSETS
+        );
+    }
+
+    #[test]
+    fn keeps_the_original_and_the_latest_resimulated_state() {
+        let mut history = StateHistory::new();
+        history.record(3, &[1]);
+        assert_eq!(history.mismatch(3), None);
+        history.record(3, &[2]);
+        history.record(3, &[4]);
+        let mismatch = history.mismatch(3).unwrap();
+        assert_eq!(mismatch.original_state, vec![1]);
+        assert_eq!(mismatch.resimulated_state, vec![4]);
+        assert_eq!(mismatch.differing_offsets, vec![0]);
+
+        history.record(3 + MAX_STATE_HISTORY, &[0]);
+        assert_eq!(history.mismatch(3), None);
+    }
+
+    #[test]
+    fn captures_the_states_of_a_sync_test_mismatch() {
+        let mut game = FlakyGame::default();
+        let mut history = StateHistory::new();
+        let mut metrics = SessionMetrics::new();
+        let mut sess = SyncTestSession::new(1, 1, 8, 2).unwrap();
+        let frame = loop {
+            match sess.advance_frame(&[vec![0]]) {
+                Ok(requests) => {
+                    let mut callbacks = RecordingCallbacks::new(&mut game, &mut history);
+                    rollback_callbacks::handle_requests(
+                        &mut callbacks,
+                        requests,
+                        InputConfirmation::ALL_CONFIRMED,
+                        &mut metrics,
+                    );
+                }
+                Err(GGRSError::MismatchedChecksum { frame }) => break frame,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        };
+        let mismatch = history.mismatch(frame).unwrap();
+        assert_ne!(mismatch.original_state, mismatch.resimulated_state);
+        assert_eq!(mismatch.differing_offsets, vec![0]);
+    }
+}
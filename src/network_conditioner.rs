@@ -0,0 +1,244 @@
+//! Simulating a bad connection on one machine by delaying, dropping, duplicating and reordering received messages.
+
+use crate::xorshift::Xorshift;
+use ggrs::{NonBlockingSocket, UdpMessage};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The extra delay of a reordered message, on top of its latency and jitter, so messages received after it are handed out first.
+const REORDER_DELAY: Duration = Duration::from_millis(30);
+
+/// The simulated conditions of a connection, the default is a perfect connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// The delay of every received message.
+    pub latency: Duration,
+    /// The maximum random delay that is added to the latency of every received message.
+    pub jitter: Duration,
+    /// The chance in percent that a received message is dropped.
+    pub packet_loss: f32,
+    /// The chance in percent that a received message is handed out twice.
+    pub duplicate_rate: f32,
+    /// The chance in percent that a received message is held back until after the messages received after it.
+    pub reorder_rate: f32,
+}
+
+impl NetworkConditions {
+    /// Returns true if messages are passed through without any changes.
+    pub fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// [NetworkConditions] that can be changed after the [NetworkConditioner] using them was handed to GGRS.
+pub type SharedNetworkConditions = Arc<Mutex<NetworkConditions>>;
+
+/// Changes the shared conditions, the conditions of a poisoned lock are still valid so they are updated anyway.
+pub fn update_conditions(
+    conditions: &SharedNetworkConditions,
+    update: impl FnOnce(&mut NetworkConditions),
+) {
+    let mut conditions = conditions.lock().unwrap_or_else(|e| e.into_inner());
+    update(&mut conditions);
+}
+
+/// Clamps a chance given in percent between `0` and `100`.
+pub fn clamp_percent(percent: f32) -> f32 {
+    if percent.is_nan() {
+        0.0
+    } else {
+        percent.clamp(0.0, 100.0)
+    }
+}
+
+/// A socket that wraps another [NonBlockingSocket] and applies [NetworkConditions] to the messages it receives.
+/// Sent messages are passed through, so conditions apply in both directions when both peers use them.
+pub struct NetworkConditioner<S, A> {
+    socket: S,
+    conditions: SharedNetworkConditions,
+    rng: Xorshift,
+    delayed_messages: Vec<(Instant, A, UdpMessage)>,
+}
+
+impl<S, A: Clone> NetworkConditioner<S, A> {
+    /// Wraps the socket, the conditions can be changed at any time through the [SharedNetworkConditions].
+    pub fn new(socket: S, conditions: SharedNetworkConditions) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::with_seed(socket, conditions, seed)
+    }
+
+    /// Wraps the socket and seeds the random decisions, which makes them reproducible.
+    pub fn with_seed(socket: S, conditions: SharedNetworkConditions, seed: u64) -> Self {
+        NetworkConditioner {
+            socket,
+            conditions,
+            rng: Xorshift::new(seed),
+            delayed_messages: Vec::new(),
+        }
+    }
+
+    /// Queues received messages according to the conditions and returns the messages of which the delay has passed, in the order they are due.
+    fn condition(
+        &mut self,
+        received_messages: Vec<(A, UdpMessage)>,
+        now: Instant,
+    ) -> Vec<(A, UdpMessage)> {
+        let conditions = *self.conditions.lock().unwrap_or_else(|e| e.into_inner());
+        if conditions.is_perfect() && self.delayed_messages.is_empty() {
+            return received_messages;
+        }
+        for (addr, msg) in received_messages {
+            if self.rng.chance(conditions.packet_loss) {
+                continue;
+            }
+            let copies = if self.rng.chance(conditions.duplicate_rate) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                let mut delay = conditions.latency + self.rng.duration(conditions.jitter);
+                if self.rng.chance(conditions.reorder_rate) {
+                    delay += REORDER_DELAY;
+                }
+                self.delayed_messages
+                    .push((now + delay, addr.clone(), msg.clone()));
+            }
+        }
+        //A stable sort keeps messages that are due at the same time in the order they were received
+        self.delayed_messages.sort_by_key(|(due, _, _)| *due);
+        let due_count = self
+            .delayed_messages
+            .iter()
+            .take_while(|(due, _, _)| *due <= now)
+            .count();
+        self.delayed_messages
+            .drain(..due_count)
+            .map(|(_, addr, msg)| (addr, msg))
+            .collect()
+    }
+}
+
+impl<S, A> NonBlockingSocket<A> for NetworkConditioner<S, A>
+where
+    S: NonBlockingSocket<A>,
+    A: Clone + Send + Sync,
+{
+    fn send_to(&mut self, msg: &UdpMessage, addr: &A) {
+        self.socket.send_to(msg, addr);
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(A, UdpMessage)> {
+        let received_messages = self.socket.receive_all_messages();
+        self.condition(received_messages, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a conditioner of which the socket is never polled, messages are passed to [NetworkConditioner::condition()] directly.
+    fn conditioner(conditions: NetworkConditions) -> NetworkConditioner<(), u32> {
+        NetworkConditioner::with_seed((), SharedNetworkConditions::new(conditions.into()), 7)
+    }
+
+    /// Returns a keep alive message, messages are told apart by their address in these tests.
+    fn message() -> UdpMessage {
+        //A magic of 0 followed by the variant index of the keep alive body
+        bincode::deserialize(&[0, 0, 6, 0, 0, 0]).unwrap()
+    }
+
+    fn messages(addrs: impl IntoIterator<Item = u32>) -> Vec<(u32, UdpMessage)> {
+        addrs.into_iter().map(|addr| (addr, message())).collect()
+    }
+
+    fn addrs(messages: Vec<(u32, UdpMessage)>) -> Vec<u32> {
+        messages.into_iter().map(|(addr, _)| addr).collect()
+    }
+
+    #[test]
+    fn passes_messages_through_a_perfect_connection() {
+        let mut conditioner = conditioner(NetworkConditions::default());
+        let now = Instant::now();
+        assert_eq!(
+            addrs(conditioner.condition(messages(0..5), now)),
+            [0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn drops_and_duplicates_messages() {
+        let now = Instant::now();
+        let mut lossy = conditioner(NetworkConditions {
+            packet_loss: 100.0,
+            ..NetworkConditions::default()
+        });
+        assert!(lossy.condition(messages(0..5), now).is_empty());
+
+        let mut duplicating = conditioner(NetworkConditions {
+            duplicate_rate: 100.0,
+            ..NetworkConditions::default()
+        });
+        assert_eq!(
+            addrs(duplicating.condition(messages(0..3), now)),
+            [0, 0, 1, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_decisions() {
+        let conditions = NetworkConditions {
+            packet_loss: 50.0,
+            duplicate_rate: 50.0,
+            ..NetworkConditions::default()
+        };
+        let now = Instant::now();
+        let received = addrs(conditioner(conditions).condition(messages(0..100), now));
+        assert_eq!(
+            addrs(conditioner(conditions).condition(messages(0..100), now)),
+            received
+        );
+        assert!(received.len() != 100 && !received.is_empty());
+    }
+
+    #[test]
+    fn hands_out_messages_once_they_are_due() {
+        let latency = Duration::from_millis(50);
+        let mut conditioner = conditioner(NetworkConditions {
+            latency,
+            jitter: Duration::from_millis(10),
+            ..NetworkConditions::default()
+        });
+        let now = Instant::now();
+        assert!(conditioner.condition(messages(0..20), now).is_empty());
+        assert!(conditioner
+            .condition(Vec::new(), now + latency - Duration::from_millis(1))
+            .is_empty());
+        let mut received = addrs(conditioner.condition(Vec::new(), now + latency * 2));
+        received.sort_unstable();
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reordered_messages_are_handed_out_after_later_messages() {
+        let mut conditioner = conditioner(NetworkConditions {
+            reorder_rate: 100.0,
+            ..NetworkConditions::default()
+        });
+        let now = Instant::now();
+        assert!(conditioner.condition(messages([0]), now).is_empty());
+        update_conditions(&conditioner.conditions, |conditions| {
+            conditions.reorder_rate = 0.0
+        });
+        let later = now + Duration::from_millis(1);
+        assert_eq!(addrs(conditioner.condition(messages([1]), later)), [1]);
+        assert_eq!(
+            addrs(conditioner.condition(messages([2]), now + REORDER_DELAY)),
+            [0, 2]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Setting a session up again for a rematch, without losing its config when that fails.
+
+/// A session that can be set up again with the config it was created with.
+/// Implemented by the session classes, so resetting a session can be tested without Godot.
+pub trait RematchSession {
+    /// Everything the session was set up with.
+    type Config: Clone;
+
+    /// Returns a copy of the config of the session, or `None` when no session was made.
+    fn config(&self) -> Option<Self::Config>;
+    /// Closes the session and opens it again with the socket of the config. Returns false if the socket could not be opened.
+    fn open(&mut self, config: &Self::Config) -> bool;
+    /// Adds the players and applies the settings of the config to the opened session. Returns false if a player could not be added.
+    fn restore(&mut self, config: &Self::Config) -> bool;
+    /// Closes a session that could not be set up again and keeps the config, so resetting can be retried.
+    fn abort(&mut self, config: Self::Config);
+}
+
+/// Errors that can occur while resetting a session for a rematch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RematchError {
+    /// No session was made, so there is no config to set up again.
+    NoSession,
+    /// The session could not be set up again, the error has already been reported by the session.
+    Failed,
+}
+
+/// Closes the session and sets it up again with the same config.
+/// When opening or restoring fails, the partial session is closed and the config is kept so resetting can be retried.
+pub fn reset_for_rematch<S: RematchSession>(session: &mut S) -> Result<(), RematchError> {
+    let config = session.config().ok_or(RematchError::NoSession)?;
+    if session.open(&config) && session.restore(&config) {
+        Ok(())
+    } else {
+        session.abort(config);
+        Err(RematchError::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A session of which the config is a list of player names, opening and restoring fail while the flags are set.
+    #[derive(Default)]
+    struct MockSession {
+        config: Option<Vec<&'static str>>,
+        players: Vec<&'static str>,
+        open: bool,
+        fail_open: bool,
+        fail_restore: bool,
+    }
+
+    impl RematchSession for MockSession {
+        type Config = Vec<&'static str>;
+
+        fn config(&self) -> Option<Self::Config> {
+            self.config.clone()
+        }
+
+        fn open(&mut self, _config: &Self::Config) -> bool {
+            self.config = None;
+            self.players.clear();
+            self.open = !self.fail_open;
+            self.open
+        }
+
+        fn restore(&mut self, config: &Self::Config) -> bool {
+            //Restoring stops halfway, like a player that could not be added
+            let restored = if self.fail_restore { 1 } else { config.len() };
+            self.players.extend_from_slice(&config[..restored]);
+            !self.fail_restore
+        }
+
+        fn abort(&mut self, config: Self::Config) {
+            self.open = false;
+            self.players.clear();
+            self.config = Some(config);
+        }
+    }
+
+    fn session() -> MockSession {
+        MockSession {
+            config: Some(vec!["local", "remote"]),
+            ..MockSession::default()
+        }
+    }
+
+    #[test]
+    fn sets_the_session_up_again() {
+        let mut session = session();
+        assert_eq!(reset_for_rematch(&mut session), Ok(()));
+        assert!(session.open);
+        assert_eq!(session.players, vec!["local", "remote"]);
+    }
+
+    #[test]
+    fn keeps_the_config_when_the_socket_could_not_be_opened() {
+        let mut session = MockSession {
+            fail_open: true,
+            ..session()
+        };
+        assert_eq!(reset_for_rematch(&mut session), Err(RematchError::Failed));
+        assert_eq!(session.config, Some(vec!["local", "remote"]));
+
+        session.fail_open = false;
+        assert_eq!(reset_for_rematch(&mut session), Ok(()));
+        assert_eq!(session.players, vec!["local", "remote"]);
+    }
+
+    #[test]
+    fn closes_a_partially_restored_session() {
+        let mut session = MockSession {
+            fail_restore: true,
+            ..session()
+        };
+        assert_eq!(reset_for_rematch(&mut session), Err(RematchError::Failed));
+        assert!(!session.open);
+        assert!(session.players.is_empty());
+        assert_eq!(session.config, Some(vec!["local", "remote"]));
+    }
+
+    #[test]
+    fn cannot_reset_without_a_session() {
+        let mut session = MockSession::default();
+        assert_eq!(
+            reset_for_rematch(&mut session),
+            Err(RematchError::NoSession)
+        );
+        assert!(!session.open);
+    }
+}
@@ -0,0 +1,380 @@
+//! Reading, writing and playing back replay files recorded from a `GodotGGRSP2PSession`.
+
+use ggrs::{Frame, GGRSRequest, GameInput, GameStateCell, PlayerHandle, NULL_FRAME};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// Every replay file starts with these bytes.
+pub const REPLAY_MAGIC: &[u8; 8] = b"GGRSRPL\0";
+/// The version of the replay format, which is increased whenever the format changes.
+pub const REPLAY_VERSION: u16 = 1;
+
+/// A player that was part of the recorded session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayPlayer {
+    /// The handle of the player.
+    pub handle: PlayerHandle,
+    /// Whether the player was local to the recording session.
+    pub is_local: bool,
+    /// The frame delay of the player.
+    pub frame_delay: u32,
+}
+
+/// The settings of the recorded session, which are written at the start of a replay file.
+/// # Format
+/// All integers are little-endian:
+/// - [REPLAY_MAGIC] followed by [REPLAY_VERSION] as a `u16`
+/// - `num_players`, `input_size` and `max_prediction` as a `u32`
+/// - the amount of players as a `u32`, followed by the `handle` (`u32`), `is_local` (`u8`) and `frame_delay` (`u32`) of each player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayHeader {
+    /// The total number of players of the session.
+    pub num_players: u32,
+    /// The input size of the session in bytes.
+    pub input_size: usize,
+    /// The max prediction frames of the session.
+    pub max_prediction: usize,
+    /// The local and remote players of the session, spectators are not recorded.
+    pub players: Vec<ReplayPlayer>,
+}
+
+impl ReplayHeader {
+    /// Writes the header in the replay format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(REPLAY_MAGIC);
+        bytes.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.num_players.to_le_bytes());
+        bytes.extend_from_slice(&(self.input_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.max_prediction as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.players.len() as u32).to_le_bytes());
+        for player in &self.players {
+            bytes.extend_from_slice(&(player.handle as u32).to_le_bytes());
+            bytes.push(player.is_local as u8);
+            bytes.extend_from_slice(&player.frame_delay.to_le_bytes());
+        }
+        writer.write_all(&bytes)
+    }
+
+    /// Reads a header in the replay format.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ReplayError> {
+        let mut magic = [0; 8];
+        read_exact(reader, &mut magic)?;
+        if &magic != REPLAY_MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let mut version = [0; 2];
+        read_exact(reader, &mut version)?;
+        match u16::from_le_bytes(version) {
+            REPLAY_VERSION => (),
+            version => return Err(ReplayError::UnsupportedVersion(version)),
+        }
+        let num_players = read_u32(reader)?;
+        let input_size = read_u32(reader)? as usize;
+        let max_prediction = read_u32(reader)? as usize;
+        let player_count = read_u32(reader)?;
+        let mut players = Vec::new();
+        for _ in 0..player_count {
+            let handle = read_u32(reader)? as PlayerHandle;
+            let mut is_local = [0; 1];
+            read_exact(reader, &mut is_local)?;
+            let frame_delay = read_u32(reader)?;
+            players.push(ReplayPlayer {
+                handle,
+                is_local: is_local[0] != 0,
+                frame_delay,
+            });
+        }
+        Ok(ReplayHeader {
+            num_players,
+            input_size,
+            max_prediction,
+            players,
+        })
+    }
+
+    /// Returns the size of a single frame record: the frame as an `i32`, followed by the inputs of all players ordered by handle.
+    pub fn frame_record_size(&self) -> usize {
+        4 + self.num_players as usize * self.input_size
+    }
+}
+
+/// Errors that can occur while reading a replay file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The file could not be read, contains the reason.
+    Io(String),
+    /// The file does not start with [REPLAY_MAGIC].
+    NotAReplay,
+    /// The file was written with a version of the replay format that is not supported.
+    UnsupportedVersion(u16),
+    /// The file ended inside the header.
+    Truncated,
+    /// The recorded frames are not consecutive, contains the first frame that is missing.
+    MissingFrame(Frame),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(reason) => write!(f, "Could not read the replay file: {}.", reason),
+            ReplayError::NotAReplay => write!(f, "The file is not a replay file."),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "Replay format version {} is not supported, expected version {}.",
+                version, REPLAY_VERSION
+            ),
+            ReplayError::Truncated => write!(f, "The header of the replay file is incomplete."),
+            ReplayError::MissingFrame(frame) => {
+                write!(f, "The replay file contains no inputs for frame {}.", frame)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<(), ReplayError> {
+    reader.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ReplayError::Truncated,
+        _ => ReplayError::Io(e.to_string()),
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, ReplayError> {
+    let mut bytes = [0; 4];
+    read_exact(reader, &mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// A replay that was read from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// The settings of the recorded session.
+    pub header: ReplayHeader,
+    /// The frame and the inputs of all players, ordered by handle, of every recorded frame.
+    pub frames: Vec<(Frame, Vec<u8>)>,
+}
+
+impl Replay {
+    /// Reads a replay, an incomplete record at the end of the file is ignored since it was being written when the recording stopped.
+    /// The recorded frames have to be consecutive, but do not need to start at frame 0 since recording can start at any frame.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ReplayError> {
+        let header = ReplayHeader::read_from(reader)?;
+        let mut records = Vec::new();
+        reader
+            .read_to_end(&mut records)
+            .map_err(|e| ReplayError::Io(e.to_string()))?;
+        let frames = records
+            .chunks_exact(header.frame_record_size())
+            .map(|record| {
+                let (frame, inputs) = record.split_at(4);
+                let frame = Frame::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+                (frame, inputs.to_vec())
+            })
+            .collect::<Vec<_>>();
+        if let Some(pair) = frames.windows(2).find(|pair| pair[1].0 != pair[0].0 + 1) {
+            return Err(ReplayError::MissingFrame(pair[0].0 + 1));
+        }
+        Ok(Replay { header, frames })
+    }
+
+    /// Returns the inputs of a recorded frame as [GameInput]s, ordered by handle.
+    pub fn game_inputs(&self, index: usize) -> Vec<GameInput> {
+        let (frame, inputs) = &self.frames[index];
+        inputs
+            .chunks_exact(self.header.input_size.max(1))
+            .map(|buffer| GameInput {
+                frame: *frame,
+                size: buffer.len(),
+                buffer: buffer.to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// Writes the inputs of confirmed frames to a replay file.
+/// Inputs are recorded for every advanced frame, but only written once their frame is confirmed, since a rollback can still change them.
+/// The writer is flushed after every write, so the file stays usable when the game crashes.
+pub struct ReplayRecorder<W: Write> {
+    writer: W,
+    record_size: usize,
+    pending_inputs: BTreeMap<Frame, Vec<u8>>,
+    last_written_frame: Frame,
+}
+
+impl<W: Write> ReplayRecorder<W> {
+    /// Writes the header and creates a [ReplayRecorder] that writes frames after it.
+    pub fn new(mut writer: W, header: &ReplayHeader) -> io::Result<Self> {
+        header.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(ReplayRecorder {
+            writer,
+            record_size: header.frame_record_size(),
+            pending_inputs: BTreeMap::new(),
+            last_written_frame: NULL_FRAME,
+        })
+    }
+
+    /// Records the inputs of all players for an advanced frame, frames that are advanced again after a rollback replace the previous inputs.
+    /// The inputs should be ordered by handle, inputs of frames that were already written are ignored.
+    pub fn record(&mut self, frame: Frame, inputs: &[Vec<u8>]) {
+        if frame > self.last_written_frame {
+            self.pending_inputs.insert(frame, inputs.concat());
+        }
+    }
+
+    /// Writes the inputs of every recorded frame up to and including `confirmed_frame`.
+    pub fn write_confirmed(&mut self, confirmed_frame: Frame) -> io::Result<()> {
+        if confirmed_frame <= self.last_written_frame {
+            return Ok(());
+        }
+        let pending_inputs = self.pending_inputs.split_off(&(confirmed_frame + 1));
+        let confirmed_inputs = std::mem::replace(&mut self.pending_inputs, pending_inputs);
+        let mut bytes = Vec::with_capacity(confirmed_inputs.len() * self.record_size);
+        for (frame, inputs) in confirmed_inputs {
+            bytes.extend_from_slice(&frame.to_le_bytes());
+            bytes.extend_from_slice(&inputs);
+            self.last_written_frame = frame;
+        }
+        self.writer.write_all(&bytes)?;
+        self.writer.flush()
+    }
+
+    /// Flushes the writer and returns it, inputs of frames that were not confirmed yet are discarded.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Plays back a [Replay] by creating the same requests a GGRS session would.
+/// States are saved every `snapshot_interval` frames, seeking loads the nearest saved state before the target and fast-forwards from there.
+pub struct ReplayPlayback {
+    replay: Replay,
+    position: usize,
+    snapshot_interval: usize,
+    snapshots: BTreeMap<usize, GameStateCell>,
+}
+
+impl ReplayPlayback {
+    /// Creates a [ReplayPlayback] that starts at the first recorded frame.
+    pub fn new(replay: Replay, snapshot_interval: usize) -> Self {
+        ReplayPlayback {
+            replay,
+            position: 0,
+            snapshot_interval: snapshot_interval.max(1),
+            snapshots: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the replay that is being played back.
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Returns the frame that will be advanced next, or the frame after the last recorded frame once the playback has finished.
+    pub fn current_frame(&self) -> Frame {
+        match self.replay.frames.get(self.position) {
+            Some((frame, _)) => *frame,
+            None => self.replay.frames.last().map_or(0, |(frame, _)| frame + 1),
+        }
+    }
+
+    /// Returns true when every recorded frame has been advanced.
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.replay.frames.len()
+    }
+
+    /// Returns the requests to advance a single frame, or `None` when the playback has finished.
+    pub fn advance(&mut self) -> Option<Vec<GGRSRequest>> {
+        if self.is_finished() {
+            return None;
+        }
+        let mut requests = Vec::new();
+        let needs_snapshot = match self.snapshots.range(..=self.position).next_back() {
+            Some((position, _)) => self.position - position >= self.snapshot_interval,
+            None => true,
+        };
+        if needs_snapshot {
+            let cell = GameStateCell::default();
+            self.snapshots.insert(self.position, cell.clone());
+            requests.push(GGRSRequest::SaveGameState {
+                cell,
+                frame: self.current_frame(),
+            });
+        }
+        requests.push(GGRSRequest::AdvanceFrame {
+            inputs: self.replay.game_inputs(self.position),
+        });
+        self.position += 1;
+        Some(requests)
+    }
+
+    /// Returns the requests to move the playback to the given frame, so that it is the frame that will be advanced next.
+    /// Seeking backwards loads the nearest saved state before the frame, after which the remaining frames are advanced.
+    pub fn seek(&mut self, frame: Frame) -> Vec<GGRSRequest> {
+        let target = self.replay.frames.partition_point(|(f, _)| *f < frame);
+        let mut requests = Vec::new();
+        if target < self.position {
+            if let Some((position, cell)) = self.snapshots.range(..=target).next_back() {
+                self.position = *position;
+                requests.push(GGRSRequest::LoadGameState {
+                    cell: cell.clone(),
+                    frame: self.current_frame(),
+                });
+            }
+        }
+        while self.position < target {
+            match self.advance() {
+                Some(advance_requests) => requests.extend(advance_requests),
+                None => break,
+            }
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> ReplayHeader {
+        ReplayHeader {
+            num_players: 2,
+            input_size: 1,
+            max_prediction: 8,
+            players: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reads_replays_that_start_after_frame_zero() {
+        let mut recorder = ReplayRecorder::new(Vec::new(), &header()).unwrap();
+        for frame in 5..8 {
+            recorder.record(frame, &[vec![frame as u8], vec![0]]);
+        }
+        recorder.write_confirmed(7).unwrap();
+        let bytes = recorder.finish().unwrap();
+        let replay = Replay::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            replay.frames,
+            vec![(5, vec![5, 0]), (6, vec![6, 0]), (7, vec![7, 0])]
+        );
+    }
+
+    #[test]
+    fn rejects_replays_with_missing_frames() {
+        let mut bytes = Vec::new();
+        header().write_to(&mut bytes).unwrap();
+        for frame in [0, 1, 3] {
+            bytes.extend_from_slice(&Frame::to_le_bytes(frame));
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        assert_eq!(
+            Replay::read_from(&mut bytes.as_slice()),
+            Err(ReplayError::MissingFrame(2))
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Forwarding GGRS requests to the game, either through Godot nodes or through any other [RollbackCallbacks] implementation.
+
+use crate::session_metrics::SessionMetrics;
+use crate::{CALLBACK_FUNC_ADVANCE_FRAME, CALLBACK_FUNC_ADVANCE_FRAME_RESIM};
+use ggrs::{Frame, GGRSRequest, GameInput, GameState, PlayerHandle, NULL_FRAME};
+use std::time::Instant;
+
+/// The callbacks that GGRS requests are forwarded to by [handle_requests()].
+/// The Godot implementation is `GodotCallbacks`, other implementations make it possible to drive a session without a Godot runtime.
+pub trait RollbackCallbacks {
+    /// Saves the game state of the given frame and returns it together with its checksum.
+    fn save_game_state(&mut self, frame: Frame) -> (Vec<u8>, u64);
+    /// Loads a game state that was returned by [RollbackCallbacks::save_game_state()].
+    fn load_game_state(&mut self, frame: Frame, state: Vec<u8>, checksum: u64);
+    /// Advances the game state by a single frame using the inputs of all players, ordered by handle.
+    /// `statuses` tells for every input whether it is confirmed, and `resimulating` is `true` when the frame is re-simulated after a rollback.
+    fn advance_frame(
+        &mut self,
+        inputs: Vec<GameInput>,
+        statuses: &[InputStatus],
+        resimulating: bool,
+    );
+}
+
+/// Whether the input of a player is known to be the input the player really made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputStatus {
+    /// The input of a local player, or an input that was received from a remote player.
+    Confirmed,
+    /// The input of a remote player was predicted by GGRS and will be corrected by a rollback if the prediction was wrong.
+    Predicted,
+    /// The player is disconnected, the input is blank.
+    Disconnected,
+}
+
+impl InputStatus {
+    /// Returns the name of the status, as it is passed to `ggrs_advance_frame`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputStatus::Confirmed => "Confirmed",
+            InputStatus::Predicted => "Predicted",
+            InputStatus::Disconnected => "Disconnected",
+        }
+    }
+}
+
+/// How the advance callback of a Godot node is called for a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvanceFrameCall {
+    /// The name of the callback function that is called.
+    pub method: &'static str,
+    /// Whether the resimulation flag is passed as a second argument.
+    pub pass_resimulation_flag: bool,
+}
+
+impl AdvanceFrameCall {
+    /// Picks the callback for a frame, re-simulated frames go to [CALLBACK_FUNC_ADVANCE_FRAME_RESIM] when the node implements it.
+    /// `resimulation_flag` tells whether the flag was enabled for [CALLBACK_FUNC_ADVANCE_FRAME] with `set_resimulation_flag()`.
+    pub fn new(resimulating: bool, has_resim_callback: bool, resimulation_flag: bool) -> Self {
+        if resimulating && has_resim_callback {
+            AdvanceFrameCall {
+                method: CALLBACK_FUNC_ADVANCE_FRAME_RESIM,
+                pass_resimulation_flag: false,
+            }
+        } else {
+            AdvanceFrameCall {
+                method: CALLBACK_FUNC_ADVANCE_FRAME,
+                pass_resimulation_flag: resimulation_flag,
+            }
+        }
+    }
+}
+
+/// What is known about the inputs of a session, used by [handle_requests()] to determine the [InputStatus] of every input.
+#[derive(Debug, Clone, Copy)]
+pub struct InputConfirmation<'a> {
+    /// The newest frame of which the inputs of all remote players have been received.
+    pub confirmed_frame: Frame,
+    /// The handles of the local players, whose inputs are never predicted.
+    pub local_handles: &'a [PlayerHandle],
+}
+
+impl InputConfirmation<'static> {
+    /// For sessions that only advance frames of which all inputs are known, like sync test and spectator sessions.
+    pub const ALL_CONFIRMED: Self = InputConfirmation {
+        confirmed_frame: Frame::MAX,
+        local_handles: &[],
+    };
+}
+
+impl InputConfirmation<'_> {
+    /// Returns the status of the input of the player with the given handle.
+    pub fn status(&self, handle: PlayerHandle, input: &GameInput) -> InputStatus {
+        if input.frame == NULL_FRAME {
+            InputStatus::Disconnected
+        } else if input.frame <= self.confirmed_frame || self.local_handles.contains(&handle) {
+            InputStatus::Confirmed
+        } else {
+            InputStatus::Predicted
+        }
+    }
+}
+
+/// The results of handling a batch of GGRS requests.
+#[derive(Debug, Clone, Default)]
+pub struct HandledRequests {
+    /// The frame and checksum of every saved state.
+    pub saved_checksums: Vec<(Frame, u64)>,
+    /// The frame and the inputs of all players, ordered by handle, of every advanced frame.
+    pub advanced_inputs: Vec<(Frame, Vec<Vec<u8>>)>,
+    /// The depth of every rollback, see [SessionMetrics::record_load()].
+    pub rollback_depths: Vec<u32>,
+}
+
+/// Forwards all requests to the callbacks and returns the saved checksums and advanced inputs.
+/// Every advance that follows a load, except the last advance of the batch, re-simulates a frame.
+pub fn handle_requests<C: RollbackCallbacks>(
+    callbacks: &mut C,
+    requests: Vec<GGRSRequest>,
+    confirmation: InputConfirmation,
+    metrics: &mut SessionMetrics,
+) -> HandledRequests {
+    let mut result = HandledRequests::default();
+    let last_advance = requests
+        .iter()
+        .rposition(|item| matches!(item, GGRSRequest::AdvanceFrame { .. }));
+    let mut loaded = false;
+    for (index, item) in requests.into_iter().enumerate() {
+        let start = Instant::now();
+        match item {
+            GGRSRequest::AdvanceFrame { inputs } => {
+                //Inputs of disconnected players have no frame, so the frame is taken from the others
+                let frame = inputs.iter().map(|i| i.frame).max().unwrap_or(NULL_FRAME);
+                let buffers = inputs.iter().map(|i| i.buffer[..i.size].to_vec()).collect();
+                let statuses: Vec<InputStatus> = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(handle, input)| confirmation.status(handle, input))
+                    .collect();
+                let resimulating = loaded && Some(index) != last_advance;
+                callbacks.advance_frame(inputs, &statuses, resimulating);
+                metrics.record_advance(start.elapsed());
+                result.advanced_inputs.push((frame, buffers));
+            }
+            GGRSRequest::LoadGameState { cell, frame } => {
+                let game_state = cell.load();
+                callbacks.load_game_state(
+                    game_state.frame,
+                    game_state.data.unwrap_or_default(),
+                    game_state.checksum,
+                );
+                let depth = metrics.record_load(frame, start.elapsed());
+                result.rollback_depths.push(depth);
+                loaded = true;
+            }
+            GGRSRequest::SaveGameState { cell, frame } => {
+                let (state, checksum) = callbacks.save_game_state(frame);
+                cell.save(GameState::new_with_checksum(frame, Some(state), checksum));
+                metrics.record_save(frame, start.elapsed());
+                result.saved_checksums.push((frame, checksum));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::{Replay, ReplayHeader, ReplayPlayback};
+    use ggrs::{GameStateCell, SyncTestSession};
+
+    /// Records every call and uses the sum of all advanced inputs as its state.
+    #[derive(Default)]
+    struct MockCallbacks {
+        state: u64,
+        calls: Vec<String>,
+    }
+
+    impl RollbackCallbacks for MockCallbacks {
+        fn save_game_state(&mut self, frame: Frame) -> (Vec<u8>, u64) {
+            self.calls.push(format!("save {}", frame));
+            (self.state.to_le_bytes().to_vec(), self.state)
+        }
+
+        fn load_game_state(&mut self, frame: Frame, state: Vec<u8>, checksum: u64) {
+            self.calls.push(format!("load {}", frame));
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&state);
+            self.state = u64::from_le_bytes(bytes);
+            assert_eq!(self.state, checksum);
+        }
+
+        fn advance_frame(
+            &mut self,
+            inputs: Vec<GameInput>,
+            statuses: &[InputStatus],
+            resimulating: bool,
+        ) {
+            let statuses: Vec<&str> = statuses.iter().map(InputStatus::as_str).collect();
+            let call = if resimulating {
+                "resimulate"
+            } else {
+                "advance"
+            };
+            self.calls.push(format!("{} {}", call, statuses.join(" ")));
+            for input in inputs {
+                self.state += input.buffer[..input.size]
+                    .iter()
+                    .map(|byte| *byte as u64)
+                    .sum::<u64>();
+            }
+        }
+    }
+
+    fn game_input(frame: Frame, byte: u8) -> GameInput {
+        GameInput {
+            frame,
+            size: 1,
+            buffer: vec![byte],
+        }
+    }
+
+    #[test]
+    fn loads_the_saved_state_and_checksum() {
+        let mut callbacks = MockCallbacks {
+            state: 42,
+            ..MockCallbacks::default()
+        };
+        let mut metrics = SessionMetrics::new();
+        let cell = GameStateCell::default();
+        let handled = handle_requests(
+            &mut callbacks,
+            vec![GGRSRequest::SaveGameState {
+                cell: cell.clone(),
+                frame: 3,
+            }],
+            InputConfirmation::ALL_CONFIRMED,
+            &mut metrics,
+        );
+        assert_eq!(handled.saved_checksums, vec![(3, 42)]);
+
+        callbacks.state = 0;
+        handle_requests(
+            &mut callbacks,
+            vec![GGRSRequest::LoadGameState { cell, frame: 3 }],
+            InputConfirmation::ALL_CONFIRMED,
+            &mut metrics,
+        );
+        assert_eq!(callbacks.state, 42);
+        assert_eq!(callbacks.calls, vec!["save 3", "load 3"]);
+    }
+
+    #[test]
+    fn takes_the_frame_of_advanced_inputs_from_connected_players() {
+        let mut callbacks = MockCallbacks::default();
+        let handled = handle_requests(
+            &mut callbacks,
+            vec![GGRSRequest::AdvanceFrame {
+                inputs: vec![game_input(NULL_FRAME, 0), game_input(7, 5)],
+            }],
+            InputConfirmation::ALL_CONFIRMED,
+            &mut SessionMetrics::new(),
+        );
+        assert_eq!(handled.advanced_inputs, vec![(7, vec![vec![0], vec![5]])]);
+        assert_eq!(callbacks.state, 5);
+        assert_eq!(callbacks.calls, vec!["advance Disconnected Confirmed"]);
+    }
+
+    #[test]
+    fn flags_predicted_inputs_and_resimulated_frames() {
+        let mut callbacks = MockCallbacks::default();
+        let cell = GameStateCell::default();
+        cell.save(GameState::new_with_checksum(5, Some(vec![0; 8]), 0));
+        let advance = |frame| GGRSRequest::AdvanceFrame {
+            inputs: vec![game_input(frame, 0), game_input(frame, 0), game_input(5, 0)],
+        };
+        let confirmation = InputConfirmation {
+            confirmed_frame: 5,
+            local_handles: &[0],
+        };
+        handle_requests(
+            &mut callbacks,
+            vec![
+                GGRSRequest::LoadGameState { cell, frame: 5 },
+                advance(5),
+                advance(6),
+                advance(7),
+            ],
+            confirmation,
+            &mut SessionMetrics::new(),
+        );
+        assert_eq!(
+            callbacks.calls,
+            vec![
+                "load 5",
+                "resimulate Confirmed Confirmed Confirmed",
+                "resimulate Confirmed Predicted Confirmed",
+                "advance Confirmed Predicted Confirmed",
+            ]
+        );
+    }
+
+    #[test]
+    fn drives_a_sync_test_session_without_mismatches() {
+        let mut callbacks = MockCallbacks::default();
+        let mut metrics = SessionMetrics::new();
+        let mut sess = SyncTestSession::new(2, 1, 8, 2).unwrap();
+        for frame in 0..20u8 {
+            let requests = sess.advance_frame(&[vec![frame], vec![1]]).unwrap();
+            handle_requests(
+                &mut callbacks,
+                requests,
+                InputConfirmation::ALL_CONFIRMED,
+                &mut metrics,
+            );
+        }
+        let expected: u64 = (0..20u64).sum::<u64>() + 20;
+        assert_eq!(callbacks.state, expected);
+        assert!(callbacks.calls.iter().any(|call| call.starts_with("load")));
+    }
+
+    /// Records the callback a Godot node would be called with for every advanced frame.
+    struct AdvanceFrameCalls {
+        has_resim_callback: bool,
+        resimulation_flag: bool,
+        calls: Vec<AdvanceFrameCall>,
+    }
+
+    impl RollbackCallbacks for AdvanceFrameCalls {
+        fn save_game_state(&mut self, _frame: Frame) -> (Vec<u8>, u64) {
+            (Vec::new(), 0)
+        }
+
+        fn load_game_state(&mut self, _frame: Frame, _state: Vec<u8>, _checksum: u64) {}
+
+        fn advance_frame(&mut self, _: Vec<GameInput>, _: &[InputStatus], resimulating: bool) {
+            self.calls.push(AdvanceFrameCall::new(
+                resimulating,
+                self.has_resim_callback,
+                self.resimulation_flag,
+            ));
+        }
+    }
+
+    #[test]
+    fn resimulated_frames_call_the_resim_callback() {
+        let mut callbacks = AdvanceFrameCalls {
+            has_resim_callback: true,
+            resimulation_flag: true,
+            calls: Vec::new(),
+        };
+        let mut sess = SyncTestSession::new(1, 1, 8, 2).unwrap();
+        for frame in 0..10u8 {
+            callbacks.calls.clear();
+            let requests = sess.advance_frame(&[vec![frame]]).unwrap();
+            handle_requests(
+                &mut callbacks,
+                requests,
+                InputConfirmation::ALL_CONFIRMED,
+                &mut SessionMetrics::new(),
+            );
+            let (newest, resimulated) = callbacks.calls.split_last().unwrap();
+            assert_eq!(newest.method, CALLBACK_FUNC_ADVANCE_FRAME);
+            assert!(newest.pass_resimulation_flag);
+            assert_eq!(resimulated.is_empty(), frame <= 2);
+            for call in resimulated {
+                assert_eq!(call.method, CALLBACK_FUNC_ADVANCE_FRAME_RESIM);
+                assert!(!call.pass_resimulation_flag);
+            }
+        }
+    }
+
+    #[test]
+    fn the_resimulation_flag_is_only_passed_when_enabled() {
+        for resimulating in [false, true] {
+            assert_eq!(
+                AdvanceFrameCall::new(resimulating, false, false),
+                AdvanceFrameCall {
+                    method: CALLBACK_FUNC_ADVANCE_FRAME,
+                    pass_resimulation_flag: false,
+                }
+            );
+            assert_eq!(
+                AdvanceFrameCall::new(resimulating, false, true),
+                AdvanceFrameCall {
+                    method: CALLBACK_FUNC_ADVANCE_FRAME,
+                    pass_resimulation_flag: true,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn only_the_newest_frame_of_a_rollback_is_not_resimulated() {
+        let mut callbacks = MockCallbacks::default();
+        let mut metrics = SessionMetrics::new();
+        let mut sess = SyncTestSession::new(1, 1, 8, 2).unwrap();
+        for frame in 0..10u8 {
+            callbacks.calls.clear();
+            let requests = sess.advance_frame(&[vec![frame]]).unwrap();
+            handle_requests(
+                &mut callbacks,
+                requests,
+                InputConfirmation::ALL_CONFIRMED,
+                &mut metrics,
+            );
+            let advances: Vec<&String> = callbacks
+                .calls
+                .iter()
+                .filter(|call| !call.starts_with("save"))
+                .collect();
+            let (newest, earlier) = advances.split_last().unwrap();
+            assert!(newest.starts_with("advance"));
+            match earlier.split_first() {
+                Some((load, resimulated)) => {
+                    assert!(load.starts_with("load"));
+                    assert!(!resimulated.is_empty());
+                    assert!(resimulated
+                        .iter()
+                        .all(|call| call.starts_with("resimulate")));
+                }
+                None => assert!(frame <= 2),
+            }
+        }
+    }
+
+    #[test]
+    fn seeking_a_replay_backwards_resimulates_the_fast_forwarded_frames() {
+        let header = ReplayHeader {
+            num_players: 1,
+            input_size: 1,
+            max_prediction: 8,
+            players: Vec::new(),
+        };
+        let frames = (0..10).map(|frame| (frame, vec![frame as u8])).collect();
+        let mut playback = ReplayPlayback::new(Replay { header, frames }, 4);
+        let mut callbacks = MockCallbacks::default();
+        let mut metrics = SessionMetrics::new();
+        let requests = playback.seek(9);
+        handle_requests(
+            &mut callbacks,
+            requests,
+            InputConfirmation::ALL_CONFIRMED,
+            &mut metrics,
+        );
+        let state = callbacks.state;
+
+        callbacks.calls.clear();
+        let requests = playback.seek(7);
+        handle_requests(
+            &mut callbacks,
+            requests,
+            InputConfirmation::ALL_CONFIRMED,
+            &mut metrics,
+        );
+        assert_eq!(
+            callbacks.calls,
+            vec![
+                "load 4",
+                "resimulate Confirmed",
+                "resimulate Confirmed",
+                "advance Confirmed"
+            ]
+        );
+        assert_eq!(callbacks.state, state - 7 - 8);
+    }
+}
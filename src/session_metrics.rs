@@ -0,0 +1,155 @@
+//! Statistics about the rollbacks of a session and the time spent in its callbacks.
+
+use ggrs::Frame;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Keeps track of how often a callback was called and how long those calls took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallbackTimings {
+    /// The amount of times the callback was called.
+    pub count: u64,
+    /// The total time spent inside the callback.
+    pub total: Duration,
+    /// The longest time spent inside a single call of the callback.
+    pub peak: Duration,
+}
+
+impl CallbackTimings {
+    /// Records a single call of the callback.
+    pub fn record(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total += elapsed;
+        self.peak = self.peak.max(elapsed);
+    }
+
+    /// Returns the average time spent inside a single call, or zero if the callback was never called.
+    pub fn average(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / count as u128) as u64),
+        }
+    }
+}
+
+/// Statistics about the GGRS requests handled by a session, used to get insight into how often and how deep rollbacks happen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionMetrics {
+    /// Timings of the `ggrs_advance_frame` callback.
+    pub advance: CallbackTimings,
+    /// Timings of the `ggrs_load_game_state` callback, every load is a rollback.
+    pub load: CallbackTimings,
+    /// Timings of the `ggrs_save_game_state` callback.
+    pub save: CallbackTimings,
+    /// Maps the depth of a rollback (the amount of frames that are re-simulated after a load) to how often a rollback of that depth happened.
+    pub rollback_depths: BTreeMap<u32, u64>,
+    frame: Frame,
+}
+
+impl SessionMetrics {
+    /// Creates empty [SessionMetrics].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an `AdvanceFrame` request.
+    pub fn record_advance(&mut self, elapsed: Duration) {
+        self.advance.record(elapsed);
+        self.frame += 1;
+    }
+
+    /// Records a `LoadGameState` request of the given frame and returns the rollback depth, which is the distance to the frame the session was at.
+    pub fn record_load(&mut self, frame: Frame, elapsed: Duration) -> u32 {
+        self.load.record(elapsed);
+        let depth = (self.frame - frame).max(0) as u32;
+        *self.rollback_depths.entry(depth).or_insert(0) += 1;
+        self.frame = frame;
+        depth
+    }
+
+    /// Records a `SaveGameState` request of the given frame.
+    pub fn record_save(&mut self, frame: Frame, elapsed: Duration) {
+        self.save.record(elapsed);
+        self.frame = frame;
+    }
+
+    /// Returns the total amount of frames that were re-simulated because of rollbacks.
+    pub fn resimulated_frames(&self) -> u64 {
+        self.rollback_depths
+            .iter()
+            .map(|(depth, count)| *depth as u64 * count)
+            .sum()
+    }
+
+    /// Returns the deepest rollback that happened, or `0` if no rollback happened.
+    pub fn max_rollback_depth(&self) -> u32 {
+        self.rollback_depths
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Resets all statistics while keeping track of the current frame.
+    pub fn reset(&mut self) {
+        *self = SessionMetrics {
+            frame: self.frame,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_callback_timings() {
+        let mut timings = CallbackTimings::default();
+        assert_eq!(timings.average(), Duration::ZERO);
+        timings.record(Duration::from_micros(10));
+        timings.record(Duration::from_micros(30));
+        assert_eq!(timings.average(), Duration::from_micros(20));
+        assert_eq!(timings.peak, Duration::from_micros(30));
+
+        //Counts that do not fit inside a u32 must not truncate the divisor
+        let timings = CallbackTimings {
+            count: 1 << 32,
+            total: Duration::from_secs(1 << 32),
+            peak: Duration::from_secs(1),
+        };
+        assert_eq!(timings.average(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn records_the_depth_of_rollbacks() {
+        let mut metrics = SessionMetrics::new();
+        for frame in 0..10 {
+            metrics.record_save(frame, Duration::ZERO);
+            metrics.record_advance(Duration::ZERO);
+        }
+        assert_eq!(metrics.record_load(7, Duration::ZERO), 3);
+        for _ in 0..3 {
+            metrics.record_advance(Duration::ZERO);
+        }
+        assert_eq!(metrics.record_load(9, Duration::ZERO), 1);
+        assert_eq!(metrics.record_load(9, Duration::ZERO), 0);
+        assert_eq!(metrics.resimulated_frames(), 4);
+        assert_eq!(metrics.max_rollback_depth(), 3);
+        assert_eq!(metrics.load.count, 3);
+    }
+
+    #[test]
+    fn reset_keeps_the_current_frame() {
+        let mut metrics = SessionMetrics::new();
+        for _ in 0..5 {
+            metrics.record_advance(Duration::from_micros(5));
+        }
+        metrics.record_load(3, Duration::ZERO);
+        metrics.reset();
+        assert_eq!(metrics.advance.count, 0);
+        assert_eq!(metrics.max_rollback_depth(), 0);
+        metrics.record_advance(Duration::ZERO);
+        assert_eq!(metrics.record_load(2, Duration::ZERO), 2);
+    }
+}
@@ -0,0 +1,169 @@
+//! The datagram transports a [WrapperSocket](crate::wrapper_socket::WrapperSocket) can send its messages over.
+//! Peers are identified by string addresses, which are socket addresses for UDP and opaque IDs for any other transport.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+const RECV_BUFFER_SIZE: usize = 4096;
+
+/// Sends and receives datagrams, which may be lost, duplicated or arrive out of order.
+pub trait Transport: Send + Sync {
+    /// Sends a datagram to the peer with the given address, failures are treated as lost datagrams.
+    fn send_to(&self, datagram: &[u8], addr: &str);
+    /// Returns all datagrams that were received since the last call, together with the address of the peer that sent them.
+    fn receive_all(&self) -> Vec<(String, Vec<u8>)>;
+}
+
+/// A non-blocking UDP socket, addresses are formatted like `"127.0.0.1:7070"` or `"[::1]:7070"`.
+/// A socket bound to an IPv6 address also reaches IPv4 peers when the system allows it, their addresses are still formatted as IPv4 addresses.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    is_ipv6: bool,
+}
+
+impl UdpTransport {
+    /// Binds a socket to the given local address, use port `0` to let the system pick a free port.
+    pub fn bind(local_addr: SocketAddr) -> std::io::Result<UdpTransport> {
+        let socket = UdpSocket::bind(local_addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpTransport {
+            socket,
+            is_ipv6: local_addr.is_ipv6(),
+        })
+    }
+
+    /// Returns the address the socket is bound to, which contains the picked port when the socket was bound to port `0`.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_to(&self, datagram: &[u8], addr: &str) {
+        let addr = match addr.parse::<SocketAddr>() {
+            //An IPv6 socket can only send to IPv4 peers through their IPv4-mapped address
+            Ok(SocketAddr::V4(v4)) if self.is_ipv6 => {
+                SocketAddr::new(v4.ip().to_ipv6_mapped().into(), v4.port())
+            }
+            Ok(addr) => addr,
+            Err(_) => return,
+        };
+        //UDP does not guarantee delivery, so failed sends are treated as lost packets
+        let _ = self.socket.send_to(datagram, addr);
+    }
+
+    fn receive_all(&self) -> Vec<(String, Vec<u8>)> {
+        let mut buffer = [0; RECV_BUFFER_SIZE];
+        let mut datagrams = Vec::new();
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((number_of_bytes, src_addr)) => {
+                    datagrams.push((format_addr(src_addr), buffer[..number_of_bytes].to_vec()))
+                }
+                //There are no more messages
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return datagrams,
+                //Datagram sockets sometimes get this error as a result of calling send_to
+                Err(ref err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(_) => return datagrams,
+            }
+        }
+    }
+}
+
+/// Formats a socket address the way [UdpTransport] identifies peers, IPv4-mapped IPv6 addresses are formatted as IPv4 addresses.
+pub fn format_addr(addr: SocketAddr) -> String {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(ip.into(), v6.port()).to_string(),
+            None => v6.to_string(),
+        },
+        SocketAddr::V4(v4) => v4.to_string(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Queues {
+    incoming: VecDeque<(String, Vec<u8>)>,
+    outgoing: VecDeque<(String, Vec<u8>)>,
+}
+
+/// A transport that only queues datagrams, so they can be carried by anything else, like the networking of Godot.
+/// Clones share the same queues: received datagrams are pushed through [QueuedTransport::push_incoming()] and sent datagrams are taken with [QueuedTransport::take_outgoing()].
+#[derive(Debug, Clone, Default)]
+pub struct QueuedTransport {
+    queues: Arc<Mutex<Queues>>,
+}
+
+impl QueuedTransport {
+    /// Creates a transport with empty queues.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a datagram that was received from the peer with the given address.
+    pub fn push_incoming(&self, addr: String, datagram: Vec<u8>) {
+        if let Ok(mut queues) = self.queues.lock() {
+            queues.incoming.push_back((addr, datagram));
+        }
+    }
+
+    /// Takes all datagrams that should be sent, in the order they were sent.
+    pub fn take_outgoing(&self) -> Vec<(String, Vec<u8>)> {
+        match self.queues.lock() {
+            Ok(mut queues) => queues.outgoing.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Transport for QueuedTransport {
+    fn send_to(&self, datagram: &[u8], addr: &str) {
+        if let Ok(mut queues) = self.queues.lock() {
+            queues
+                .outgoing
+                .push_back((addr.to_owned(), datagram.to_vec()));
+        }
+    }
+
+    fn receive_all(&self) -> Vec<(String, Vec<u8>)> {
+        match self.queues.lock() {
+            Ok(mut queues) => queues.incoming.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_ipv4_mapped_addresses_as_ipv4() {
+        let mapped: SocketAddr = "[::ffff:192.168.1.5]:7070".parse().unwrap();
+        assert_eq!(format_addr(mapped), "192.168.1.5:7070");
+        let ipv6: SocketAddr = "[::1]:7070".parse().unwrap();
+        assert_eq!(format_addr(ipv6), "[::1]:7070");
+    }
+
+    #[test]
+    fn udp_transports_bound_to_port_zero_can_reach_each_other() {
+        let first = UdpTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let second = UdpTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+        assert_ne!(first_addr.port(), 0);
+
+        first.send_to(&[1, 2, 3], &second_addr.to_string());
+        let mut received = Vec::new();
+        for _ in 0..100 {
+            received = second.receive_all();
+            if !received.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(received, vec![(first_addr.to_string(), vec![1, 2, 3])]);
+    }
+}
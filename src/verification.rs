@@ -0,0 +1,183 @@
+//! Verifies the determinism of recorded replays without launching Godot, see the `verify_replay` binary.
+
+use crate::desync_detection;
+use crate::replay::Replay;
+use crate::rollback_callbacks::{self, InputConfirmation, InputStatus, RollbackCallbacks};
+use crate::session_metrics::SessionMetrics;
+use ggrs::{Frame, GGRSError, GameInput, SyncTestSession};
+use std::collections::BTreeMap;
+
+/// A deterministic simulation written in Rust that can be driven by the requests of a GGRS session.
+pub trait Simulation {
+    /// Advances the simulation by a single frame using the inputs of all players, ordered by handle.
+    fn advance(&mut self, inputs: &[GameInput]);
+    /// Returns the current state of the simulation.
+    fn save(&self) -> Vec<u8>;
+    /// Restores a state that was returned by [Simulation::save()].
+    fn load(&mut self, state: &[u8]);
+}
+
+/// A [Simulation] whose state is a hash of every input it has been advanced with, including the frame and size of each input.
+/// Every frame is checked against the inputs that are recorded for it, which makes it suitable to validate the inputs and the order of requests GGRS creates.
+#[derive(Debug, Clone, Default)]
+pub struct InputHashSimulation {
+    frame: Frame,
+    hash: u64,
+    recorded_inputs: BTreeMap<Frame, Vec<u8>>,
+    mismatched_frames: Vec<Frame>,
+}
+
+impl InputHashSimulation {
+    /// Creates an [InputHashSimulation] that checks the inputs of a [Replay] against the frames they were recorded at.
+    /// The simulation starts at the first recorded frame, since recording can start at any frame of a session.
+    pub fn new(replay: &Replay) -> Self {
+        InputHashSimulation {
+            frame: replay.frames.first().map_or(0, |(frame, _)| *frame),
+            recorded_inputs: replay.frames.iter().cloned().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the frames at which the simulation was advanced with inputs that differ from the recorded inputs.
+    pub fn mismatched_frames(&self) -> &[Frame] {
+        &self.mismatched_frames
+    }
+}
+
+impl Simulation for InputHashSimulation {
+    fn advance(&mut self, inputs: &[GameInput]) {
+        let mut bytes = self.hash.to_le_bytes().to_vec();
+        let mut buffers = Vec::new();
+        for input in inputs {
+            bytes.extend_from_slice(&input.frame.to_le_bytes());
+            bytes.extend_from_slice(&(input.size as u32).to_le_bytes());
+            bytes.extend_from_slice(&input.buffer[..input.size]);
+            buffers.extend_from_slice(&input.buffer[..input.size]);
+        }
+        let expected = self.recorded_inputs.get(&self.frame);
+        if expected != Some(&buffers) && !self.mismatched_frames.contains(&self.frame) {
+            self.mismatched_frames.push(self.frame);
+        }
+        self.hash = desync_detection::checksum(&bytes);
+        self.frame += 1;
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut state = self.frame.to_le_bytes().to_vec();
+        state.extend_from_slice(&self.hash.to_le_bytes());
+        state
+    }
+
+    fn load(&mut self, state: &[u8]) {
+        let mut frame = [0; 4];
+        let mut hash = [0; 8];
+        frame.copy_from_slice(&state[..4]);
+        hash.copy_from_slice(&state[4..12]);
+        self.frame = Frame::from_le_bytes(frame);
+        self.hash = u64::from_le_bytes(hash);
+    }
+}
+
+/// The outcome of [verify_replay()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// Every frame was advanced without a checksum mismatch, contains the amount of frames.
+    Deterministic(usize),
+    /// The checksum of a frame differed after it was re-simulated, contains the frame.
+    Mismatch(Frame),
+}
+
+/// Feeds the inputs of a [Replay] through a [SyncTestSession] which drives the [Simulation].
+/// Every frame is rolled back and re-simulated `check_distance` frames later, the first frame of which the checksum changed is reported.
+/// Frames within `check_distance` of the end of the replay are never re-simulated.
+/// # Errors
+/// - Returns an error if the [SyncTestSession] could not be created, for example when `check_distance` is not lower than the max prediction of the replay
+pub fn verify_replay<S: Simulation>(
+    replay: &Replay,
+    simulation: &mut S,
+    check_distance: usize,
+) -> Result<Verification, GGRSError> {
+    let header = &replay.header;
+    let mut callbacks = SimulationCallbacks(simulation);
+    let mut metrics = SessionMetrics::new();
+    let mut sess = SyncTestSession::new(
+        header.num_players,
+        header.input_size,
+        header.max_prediction,
+        check_distance,
+    )?;
+    for index in 0..replay.frames.len() {
+        let all_inputs = replay
+            .game_inputs(index)
+            .into_iter()
+            .map(|input| input.buffer)
+            .collect::<Vec<_>>();
+        match sess.advance_frame(&all_inputs) {
+            Ok(requests) => {
+                rollback_callbacks::handle_requests(
+                    &mut callbacks,
+                    requests,
+                    InputConfirmation::ALL_CONFIRMED,
+                    &mut metrics,
+                );
+            }
+            Err(GGRSError::MismatchedChecksum { frame }) => {
+                return Ok(Verification::Mismatch(frame))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Verification::Deterministic(replay.frames.len()))
+}
+
+/// Forwards requests to a [Simulation] through the same request handling as the session classes.
+struct SimulationCallbacks<'a, S: Simulation>(&'a mut S);
+
+impl<S: Simulation> RollbackCallbacks for SimulationCallbacks<'_, S> {
+    fn save_game_state(&mut self, _frame: Frame) -> (Vec<u8>, u64) {
+        let state = self.0.save();
+        let checksum = desync_detection::checksum(&state);
+        (state, checksum)
+    }
+
+    fn load_game_state(&mut self, _frame: Frame, state: Vec<u8>, _checksum: u64) {
+        self.0.load(&state);
+    }
+
+    fn advance_frame(&mut self, inputs: Vec<GameInput>, _: &[InputStatus], _: bool) {
+        self.0.advance(&inputs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayHeader;
+
+    #[test]
+    fn checks_inputs_against_the_recorded_frames() {
+        let replay = Replay {
+            header: ReplayHeader {
+                num_players: 2,
+                input_size: 1,
+                max_prediction: 8,
+                players: Vec::new(),
+            },
+            frames: (5..20).map(|frame| (frame, vec![frame as u8, 1])).collect(),
+        };
+        let mut simulation = InputHashSimulation::new(&replay);
+        assert_eq!(
+            verify_replay(&replay, &mut simulation, 2),
+            Ok(Verification::Deterministic(15))
+        );
+        assert!(simulation.mismatched_frames().is_empty());
+
+        //Inputs that were recorded for other frames are reported
+        let mut shifted = replay.clone();
+        shifted.frames.iter_mut().for_each(|(frame, _)| *frame -= 5);
+        let mut simulation = InputHashSimulation::new(&shifted);
+        simulation.frame = 5;
+        verify_replay(&replay, &mut simulation, 2).unwrap();
+        assert_eq!(simulation.mismatched_frames().first(), Some(&5));
+    }
+}
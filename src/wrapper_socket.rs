@@ -0,0 +1,210 @@
+//! The socket used by `GodotGGRSP2PSession` and `GodotGGRSP2PSpectatorSession`, which carries desync detection reports next to the messages of GGRS.
+
+use crate::transport::Transport;
+use ggrs::{Frame, NonBlockingSocket, UdpMessage};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// The version of the datagram format, peers with another version can not understand each other.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Every datagram starts with this header, so datagrams of plain GGRS sockets and other versions are recognized.
+const PROTOCOL_HEADER: [u8; 2] = [b'G', PROTOCOL_VERSION];
+/// The byte after the header tells what kind of message follows.
+const MESSAGE_TAG_GGRS: u8 = 0;
+const MESSAGE_TAG_CHECKSUM: u8 = 1;
+
+/// The checksum of a confirmed frame, sent to peers to detect desyncs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumReport {
+    /// The frame the checksum belongs to.
+    pub frame: Frame,
+    /// The checksum of the saved state of the frame.
+    pub checksum: u64,
+}
+
+impl ChecksumReport {
+    const SIZE: usize = 12;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.frame.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        let mut frame = [0; 4];
+        let mut checksum = [0; 8];
+        frame.copy_from_slice(&bytes[..4]);
+        checksum.copy_from_slice(&bytes[4..]);
+        Some(ChecksumReport {
+            frame: Frame::from_le_bytes(frame),
+            checksum: u64::from_le_bytes(checksum),
+        })
+    }
+}
+
+type ReceivedReports = Arc<Mutex<Vec<(String, ChecksumReport)>>>;
+type IncompatiblePeers = Arc<Mutex<Vec<String>>>;
+
+/// A socket that is handed to GGRS, which also carries [ChecksumReport]s next to the messages of GGRS over a [Transport].
+/// Received reports are queued for the [ChecksumChannel] that was created alongside the socket.
+/// Datagrams that were not sent by a [WrapperSocket] of the same [PROTOCOL_VERSION] are dropped, and their senders are queued for the [ChecksumChannel] as well.
+pub struct WrapperSocket {
+    transport: Arc<dyn Transport>,
+    received_reports: ReceivedReports,
+    incompatible_peers: IncompatiblePeers,
+    known_incompatible_peers: HashSet<String>,
+}
+
+/// Sends and receives [ChecksumReport]s over the same transport as the [WrapperSocket] it was created with.
+pub struct ChecksumChannel {
+    transport: Arc<dyn Transport>,
+    received_reports: ReceivedReports,
+    incompatible_peers: IncompatiblePeers,
+}
+
+impl WrapperSocket {
+    /// Creates a socket that sends its messages over the given transport.
+    pub fn new(transport: Arc<dyn Transport>) -> (WrapperSocket, ChecksumChannel) {
+        let received_reports = ReceivedReports::default();
+        let incompatible_peers = IncompatiblePeers::default();
+        let channel = ChecksumChannel {
+            transport: transport.clone(),
+            received_reports: received_reports.clone(),
+            incompatible_peers: incompatible_peers.clone(),
+        };
+        let socket = WrapperSocket {
+            transport,
+            received_reports,
+            incompatible_peers,
+            known_incompatible_peers: HashSet::new(),
+        };
+        (socket, channel)
+    }
+
+    /// Queues the address of a peer that sent a datagram in another format, every peer is only queued once.
+    fn report_incompatible_peer(&mut self, addr: String) {
+        if self.known_incompatible_peers.insert(addr.clone()) {
+            if let Ok(mut peers) = self.incompatible_peers.lock() {
+                peers.push(addr);
+            }
+        }
+    }
+}
+
+impl NonBlockingSocket<String> for WrapperSocket {
+    fn send_to(&mut self, msg: &UdpMessage, addr: &String) {
+        let mut buf = message_header(MESSAGE_TAG_GGRS);
+        if bincode::serialize_into(&mut buf, msg).is_ok() {
+            self.transport.send_to(&buf, addr);
+        }
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(String, UdpMessage)> {
+        let mut received_messages = Vec::new();
+        for (src_addr, datagram) in self.transport.receive_all() {
+            let message = match datagram.strip_prefix(&PROTOCOL_HEADER[..]) {
+                Some(message) => message,
+                None => {
+                    self.report_incompatible_peer(src_addr);
+                    continue;
+                }
+            };
+            match message.split_first() {
+                Some((&MESSAGE_TAG_GGRS, body)) => {
+                    if let Ok(msg) = bincode::deserialize(body) {
+                        received_messages.push((src_addr, msg));
+                    }
+                }
+                Some((&MESSAGE_TAG_CHECKSUM, body)) => {
+                    if let (Some(report), Ok(mut reports)) = (
+                        ChecksumReport::from_bytes(body),
+                        self.received_reports.lock(),
+                    ) {
+                        reports.push((src_addr, report));
+                    }
+                }
+                _ => (),
+            }
+        }
+        received_messages
+    }
+}
+
+/// Returns the start of a datagram containing a message of the given kind.
+fn message_header(tag: u8) -> Vec<u8> {
+    let mut buf = PROTOCOL_HEADER.to_vec();
+    buf.push(tag);
+    buf
+}
+
+impl ChecksumChannel {
+    /// Sends a report to the given address.
+    pub fn send(&self, report: ChecksumReport, addr: &str) {
+        let mut buf = message_header(MESSAGE_TAG_CHECKSUM);
+        buf.extend_from_slice(&report.to_bytes());
+        self.transport.send_to(&buf, addr);
+    }
+
+    /// Returns the addresses of peers that sent datagrams which were not made by a [WrapperSocket] of the same [PROTOCOL_VERSION], since the last call.
+    /// These are usually peers running an older version of GodotGGRS, every peer is only returned once.
+    pub fn incompatible_peers(&self) -> Vec<String> {
+        match self.incompatible_peers.lock() {
+            Ok(mut peers) => peers.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns all reports that were received since the last call.
+    pub fn receive(&self) -> Vec<(String, ChecksumReport)> {
+        match self.received_reports.lock() {
+            Ok(mut reports) => reports.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::QueuedTransport;
+
+    #[test]
+    fn carries_checksum_reports_next_to_ggrs_messages() {
+        let transport = QueuedTransport::new();
+        let (_, sender) = WrapperSocket::new(Arc::new(transport.clone()));
+        let report = ChecksumReport {
+            frame: 12,
+            checksum: 34,
+        };
+        sender.send(report, "peer");
+        let (mut socket, receiver) = WrapperSocket::new(Arc::new(transport.clone()));
+        for (_, datagram) in transport.take_outgoing() {
+            transport.push_incoming("host".to_owned(), datagram);
+        }
+        assert!(socket.receive_all_messages().is_empty());
+        assert_eq!(receiver.receive(), vec![("host".to_owned(), report)]);
+        assert!(receiver.incompatible_peers().is_empty());
+    }
+
+    #[test]
+    fn reports_peers_of_other_versions_once() {
+        let transport = QueuedTransport::new();
+        let (mut socket, channel) = WrapperSocket::new(Arc::new(transport.clone()));
+        //A plain GGRS socket sends its messages without a header
+        transport.push_incoming("old".to_owned(), vec![MESSAGE_TAG_GGRS, 1, 2, 3]);
+        transport.push_incoming("old".to_owned(), vec![MESSAGE_TAG_CHECKSUM; 13]);
+        transport.push_incoming("newer".to_owned(), vec![b'G', PROTOCOL_VERSION + 1, 0]);
+        assert!(socket.receive_all_messages().is_empty());
+        assert!(channel.receive().is_empty());
+        assert_eq!(channel.incompatible_peers(), vec!["old", "newer"]);
+
+        transport.push_incoming("old".to_owned(), vec![MESSAGE_TAG_GGRS]);
+        socket.receive_all_messages();
+        assert!(channel.incompatible_peers().is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+//! A small seeded random number generator, used wherever reproducible randomness is good enough.
+
+use std::time::Duration;
+
+/// A xorshift random number generator, the same seed always produces the same numbers.
+#[derive(Debug, Clone)]
+pub struct Xorshift(u64);
+
+impl Xorshift {
+    /// Creates a generator from the given seed.
+    pub fn new(seed: u64) -> Self {
+        //The state of a xorshift generator should never be zero
+        Xorshift(seed.max(1))
+    }
+
+    /// Returns the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns true with the given chance in percent.
+    pub fn chance(&mut self, percent: f32) -> bool {
+        percent > 0.0 && (self.next_u64() % 10_000) < (percent * 100.0) as u64
+    }
+
+    /// Returns a random duration between zero and the given maximum, inclusive.
+    pub fn duration(&mut self, max: Duration) -> Duration {
+        match max.as_micros() as u64 {
+            0 => Duration::ZERO,
+            max => Duration::from_micros(self.next_u64() % (max + 1)),
+        }
+    }
+
+    /// Returns the given amount of random bytes.
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| (self.next_u64() >> 56) as u8).collect()
+    }
+}
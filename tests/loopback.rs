@@ -0,0 +1,342 @@
+//! Runs two `P2PSession`s and a spectator over loopback within one process and checks that every end simulates the same game.
+//! The players exchange the checksums of confirmed frames like `GodotGGRSP2PSession` does, so no desync may be detected.
+
+use ggrs::{Frame, GameInput, P2PSession, P2PSpectatorSession, PlayerType, SessionState};
+use godot_ggrs_wrapper::desync_detection::DesyncDetector;
+use godot_ggrs_wrapper::network_conditioner::{
+    NetworkConditioner, NetworkConditions, SharedNetworkConditions,
+};
+use godot_ggrs_wrapper::rollback_callbacks::{
+    self, InputConfirmation, InputStatus, RollbackCallbacks,
+};
+use godot_ggrs_wrapper::session_metrics::SessionMetrics;
+use godot_ggrs_wrapper::transport::{QueuedTransport, UdpTransport};
+use godot_ggrs_wrapper::wrapper_socket::{ChecksumChannel, WrapperSocket};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const NUM_PLAYERS: u32 = 2;
+const INPUT_SIZE: usize = 1;
+const MAX_PREDICTION: usize = 8;
+const FRAMES: Frame = 120;
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A deterministic game in which every player moves by its input, the checksum of every simulated frame is kept.
+#[derive(Default)]
+struct ToyGame {
+    frame: Frame,
+    positions: [i64; NUM_PLAYERS as usize],
+    checksums: BTreeMap<Frame, u64>,
+}
+
+impl ToyGame {
+    fn state(&self) -> Vec<u8> {
+        let mut state = self.frame.to_le_bytes().to_vec();
+        for position in self.positions {
+            state.extend_from_slice(&position.to_le_bytes());
+        }
+        state
+    }
+
+    fn checksum(&self) -> u64 {
+        //FNV-1a
+        self.state().iter().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+impl RollbackCallbacks for ToyGame {
+    fn save_game_state(&mut self, frame: Frame) -> (Vec<u8>, u64) {
+        assert_eq!(frame, self.frame);
+        (self.state(), self.checksum())
+    }
+
+    fn load_game_state(&mut self, frame: Frame, state: Vec<u8>, checksum: u64) {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&state[..4]);
+        self.frame = Frame::from_le_bytes(bytes);
+        for (index, position) in self.positions.iter_mut().enumerate() {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&state[4 + index * 8..12 + index * 8]);
+            *position = i64::from_le_bytes(bytes);
+        }
+        assert_eq!(frame, self.frame);
+        assert_eq!(checksum, self.checksum());
+    }
+
+    fn advance_frame(&mut self, inputs: Vec<GameInput>, _: &[InputStatus], _: bool) {
+        for (position, input) in self.positions.iter_mut().zip(inputs) {
+            *position = position.wrapping_mul(3) + input.buffer[0] as i64 - 2;
+        }
+        self.frame += 1;
+        self.checksums.insert(self.frame, self.checksum());
+    }
+}
+
+/// The input of a player at a frame, which changes often enough to cause rollbacks.
+fn input(handle: usize, frame: Frame) -> Vec<u8> {
+    vec![((frame as usize * 7 + handle * 3) % 5) as u8]
+}
+
+/// The address of any free port on the loopback interface.
+fn free_loopback_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 0))
+}
+
+/// Binds a socket to the given address and returns it with its checksum channel and the address it was bound to.
+fn bind_loopback(addr: SocketAddr) -> ((WrapperSocket, ChecksumChannel), SocketAddr) {
+    let transport = UdpTransport::bind(addr).expect("could not bind the socket");
+    let addr = transport.local_addr().unwrap();
+    (WrapperSocket::new(Arc::new(transport)), addr)
+}
+
+/// The session of a player, together with what it needs to exchange checksums with the remote player.
+struct Player {
+    sess: P2PSession<Vec<u8>, String>,
+    checksum_channel: ChecksumChannel,
+    remote_addr: String,
+    desync_detector: DesyncDetector,
+}
+
+/// Creates a player that plays against the remote address and sends its inputs to the spectator address, if any.
+fn create_player(
+    local_handle: usize,
+    (socket, checksum_channel): (WrapperSocket, ChecksumChannel),
+    remote_addr: String,
+    spectator_addr: Option<String>,
+    conditions: NetworkConditions,
+) -> Player {
+    let socket = NetworkConditioner::with_seed(
+        socket,
+        SharedNetworkConditions::new(conditions.into()),
+        local_handle as u64 + 1,
+    );
+    let mut sess = P2PSession::new_with_socket(NUM_PLAYERS, INPUT_SIZE, MAX_PREDICTION, socket);
+    sess.add_player(PlayerType::Local, local_handle).unwrap();
+    sess.add_player(PlayerType::Remote(remote_addr.clone()), 1 - local_handle)
+        .unwrap();
+    if let Some(addr) = spectator_addr {
+        sess.add_player(PlayerType::Spectator(addr), NUM_PLAYERS as usize)
+            .unwrap();
+    }
+    sess.set_frame_delay(2, local_handle).unwrap();
+    sess.start_session().unwrap();
+    Player {
+        sess,
+        checksum_channel,
+        remote_addr,
+        desync_detector: DesyncDetector::new(),
+    }
+}
+
+/// Plays a match over UDP between two players, watched by a spectator, on the given addresses of which the port can be `0`.
+/// Returns the addresses the sessions were bound to and the metrics of all ends combined.
+fn play_udp_match(
+    addrs: [SocketAddr; 3],
+    conditions: NetworkConditions,
+) -> ([SocketAddr; 3], SessionMetrics) {
+    let (first_socket, first_addr) = bind_loopback(addrs[0]);
+    let (second_socket, second_addr) = bind_loopback(addrs[1]);
+    let (spectator_socket, spectator_addr) = bind_loopback(addrs[2]);
+    let players = [
+        create_player(
+            0,
+            first_socket,
+            second_addr.to_string(),
+            Some(spectator_addr.to_string()),
+            conditions,
+        ),
+        create_player(1, second_socket, first_addr.to_string(), None, conditions),
+    ];
+    let spectator = P2PSpectatorSession::new_with_socket(
+        NUM_PLAYERS,
+        INPUT_SIZE,
+        spectator_socket.0,
+        first_addr.to_string(),
+    );
+    let metrics = play_match(players, spectator, || ());
+    ([first_addr, second_addr, spectator_addr], metrics)
+}
+
+/// Plays a match between two players and a spectator, calling `pump` before every poll of the sessions.
+/// Asserts that every end simulated the same game without the players detecting a desync, and returns the metrics of all ends combined.
+fn play_match(
+    mut players: [Player; 2],
+    mut spectator: P2PSpectatorSession<String>,
+    mut pump: impl FnMut(),
+) -> SessionMetrics {
+    spectator.start_session().unwrap();
+
+    let start = Instant::now();
+    while players
+        .iter()
+        .any(|player| player.sess.current_state() != SessionState::Running)
+        || spectator.current_state() != SessionState::Running
+    {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "the sessions did not synchronize"
+        );
+        pump();
+        for player in players.iter_mut() {
+            player.sess.poll_remote_clients();
+        }
+        spectator.poll_remote_clients();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let mut games = [ToyGame::default(), ToyGame::default()];
+    let mut spectator_game = ToyGame::default();
+    let mut metrics = SessionMetrics::new();
+    let mut compared_reports = 0;
+    while players
+        .iter()
+        .any(|player| player.sess.confirmed_frame() < FRAMES)
+        || spectator_game.frame < FRAMES
+    {
+        assert!(start.elapsed() < TIMEOUT, "the sessions did not advance");
+        pump();
+        for (handle, (player, game)) in players.iter_mut().zip(games.iter_mut()).enumerate() {
+            let sess = &mut player.sess;
+            //The sessions can run ahead of each other until the prediction threshold is reached
+            if let Ok(requests) = sess.advance_frame(handle, &input(handle, game.frame)) {
+                let confirmation = InputConfirmation {
+                    confirmed_frame: sess.confirmed_frame(),
+                    local_handles: &[handle],
+                };
+                let handled =
+                    rollback_callbacks::handle_requests(game, requests, confirmation, &mut metrics);
+                for (frame, checksum) in handled.saved_checksums {
+                    player.desync_detector.record_local(frame, checksum);
+                }
+                for report in player.desync_detector.confirm(sess.confirmed_frame()) {
+                    player.checksum_channel.send(report, &player.remote_addr);
+                }
+            }
+            sess.events().for_each(drop);
+            for (_, report) in player.checksum_channel.receive() {
+                player.desync_detector.record_remote(1 - handle, report);
+                compared_reports += 1;
+            }
+            let desyncs = player.desync_detector.detect();
+            assert!(
+                desyncs.is_empty(),
+                "player {} detected {:?}",
+                handle,
+                desyncs
+            );
+        }
+        if let Ok(requests) = spectator.advance_frame() {
+            rollback_callbacks::handle_requests(
+                &mut spectator_game,
+                requests,
+                InputConfirmation::ALL_CONFIRMED,
+                &mut metrics,
+            );
+        }
+        spectator.events().for_each(drop);
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    for frame in 1..=FRAMES {
+        let expected = spectator_game.checksums[&frame];
+        assert_eq!(games[0].checksums[&frame], expected, "frame {}", frame);
+        assert_eq!(games[1].checksums[&frame], expected, "frame {}", frame);
+    }
+    assert!(compared_reports > 0, "no checksums were exchanged");
+    metrics
+}
+
+#[test]
+fn two_players_and_a_spectator_simulate_the_same_game() {
+    play_udp_match([free_loopback_addr(); 3], NetworkConditions::default());
+}
+
+#[test]
+fn bad_network_conditions_cause_rollbacks_without_desyncs() {
+    let (_, metrics) = play_udp_match(
+        [free_loopback_addr(); 3],
+        NetworkConditions {
+            latency: Duration::from_millis(20),
+            jitter: Duration::from_millis(10),
+            packet_loss: 10.0,
+            duplicate_rate: 5.0,
+            reorder_rate: 5.0,
+        },
+    );
+    assert!(metrics.load.count > 0);
+}
+
+#[test]
+fn sessions_can_send_their_messages_over_a_queued_transport() {
+    let ids = ["player 0", "player 1", "spectator"];
+    let transports = [
+        QueuedTransport::new(),
+        QueuedTransport::new(),
+        QueuedTransport::new(),
+    ];
+    let socket = |index: usize| WrapperSocket::new(Arc::new(transports[index].clone()));
+    let players = [
+        create_player(
+            0,
+            socket(0),
+            ids[1].to_owned(),
+            Some(ids[2].to_owned()),
+            NetworkConditions::default(),
+        ),
+        create_player(
+            1,
+            socket(1),
+            ids[0].to_owned(),
+            None,
+            NetworkConditions::default(),
+        ),
+    ];
+    let spectator = P2PSpectatorSession::new_with_socket(
+        NUM_PLAYERS,
+        INPUT_SIZE,
+        socket(2).0,
+        ids[0].to_owned(),
+    );
+    //Delivers every datagram right away, like a transport node in Godot would after a while
+    let pump = || {
+        for (sender, transport) in transports.iter().enumerate() {
+            for (addr, datagram) in transport.take_outgoing() {
+                let recipient = ids.iter().position(|id| *id == addr).unwrap();
+                transports[recipient].push_incoming(ids[sender].to_owned(), datagram);
+            }
+        }
+    };
+    play_match(players, spectator, pump);
+}
+
+#[test]
+fn closing_a_session_releases_its_port() {
+    let transport = UdpTransport::bind(free_loopback_addr()).expect("could not bind the socket");
+    let local_addr = transport.local_addr().unwrap();
+    let socket = WrapperSocket::new(Arc::new(transport));
+    let (_remote_socket, remote_addr) = bind_loopback(free_loopback_addr());
+    let player = create_player(
+        0,
+        socket,
+        remote_addr.to_string(),
+        None,
+        NetworkConditions::default(),
+    );
+    //The checksum channel shares the transport with the socket of the session, so both have to be dropped
+    drop(player);
+    assert!(UdpTransport::bind(local_addr).is_ok());
+}
+
+/// Plays a rematch the way `reset_for_rematch()` sets it up: new sessions with the same players on the ports of the closed sessions.
+/// `GodotGGRSP2PSession::close()` and the replay of its `SessionConfig` need a Godot node, so they are not covered by these tests.
+#[test]
+fn a_rematch_can_be_played_on_the_ports_of_the_closed_sessions() {
+    let (addrs, _) = play_udp_match([free_loopback_addr(); 3], NetworkConditions::default());
+    let (rematch_addrs, _) = play_udp_match(addrs, NetworkConditions::default());
+    assert_eq!(rematch_addrs, addrs);
+}